@@ -0,0 +1,571 @@
+// everything needed to bring an event back up somewhere else, e.g. for post-event archives
+use std::collections::HashMap;
+
+use axum::{
+    extract::{DefaultBodyLimit, Query, State as StateE},
+    routing::get,
+    Json, Router,
+};
+use chrono::{NaiveDateTime, Utc};
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    db::{update_chall_cache_tx, DeploymentStrategy},
+    extractors::Admin,
+    Error, Result, State,
+};
+
+// bump when the archive format changes in a way older imports can't read
+const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveChallenge {
+    public_id: String,
+    name: String,
+    author: String,
+    description: String,
+    points_min: i32,
+    points_max: i32,
+    points_decay: Option<f64>,
+    flag: String,
+    attachments: Value,
+    visible: bool,
+    strategy: DeploymentStrategy,
+    category: String,
+    group: Option<String>,
+    difficulty: Option<String>,
+    tags: Vec<String>,
+    release_at: Option<NaiveDateTime>,
+    release_announced: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveTeam {
+    public_id: String,
+    name: String,
+    email: String,
+    division: Option<String>,
+    created_at: NaiveDateTime,
+    extra_data: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveUser {
+    public_id: String,
+    name: String,
+    email: String,
+    team: Option<String>,
+    captain: bool,
+    joined_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+}
+
+// teams, users and challenges are referenced by public id
+#[derive(Serialize, Deserialize)]
+struct ArchiveSubmission {
+    team: String,
+    // None for submissions made with the team token
+    user: Option<String>,
+    challenge: String,
+    submission: String,
+    is_correct: bool,
+    created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveDeployment {
+    public_id: String,
+    team: Option<String>,
+    challenge: String,
+    data: Option<Value>,
+    created_at: NaiveDateTime,
+    expired_at: Option<NaiveDateTime>,
+    destroyed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    version: u32,
+    exported_at: NaiveDateTime,
+    // the raw file, so comments survive
+    event_toml: String,
+    categories: Vec<String>,
+    groups: Vec<String>,
+    challenges: Vec<ArchiveChallenge>,
+    teams: Vec<ArchiveTeam>,
+    // missing in archives from before user accounts
+    #[serde(default)]
+    users: Vec<ArchiveUser>,
+    submissions: Vec<ArchiveSubmission>,
+    deployments: Vec<ArchiveDeployment>,
+}
+
+#[derive(Deserialize)]
+struct ExportArchiveQuery {
+    // replaces team and user ids, names, emails and extra data, and drops the text of wrong
+    // submissions
+    #[serde(default)]
+    anonymize: bool,
+}
+
+async fn export_archive(
+    StateE(state): StateE<State>,
+    _: Admin,
+    Query(query): Query<ExportArchiveQuery>,
+) -> Result<Json<Archive>> {
+    let event_toml = tokio::fs::read_to_string(&state.config.event_path).await?;
+
+    let categories = sqlx::query_scalar!("SELECT name FROM categories ORDER BY id")
+        .fetch_all(&state.db)
+        .await?;
+    let groups = sqlx::query_scalar!("SELECT name FROM challenge_groups ORDER BY id")
+        .fetch_all(&state.db)
+        .await?;
+
+    let challenges = sqlx::query_as!(
+        ArchiveChallenge,
+        r#"
+        SELECT c.public_id, c.name, c.author, c.description, c.points_min, c.points_max,
+            c.points_decay, c.flag, c.attachments, c.visible,
+            c.strategy AS "strategy: DeploymentStrategy", cat.name AS category, g.name AS "group?",
+            c.difficulty, c.release_at, c.release_announced,
+            COALESCE(
+                (SELECT array_agg(t.name ORDER BY t.name) FROM challenge_tags ct JOIN tags t ON t.id = ct.tag_id
+                WHERE ct.challenge_id = c.id),
+                '{}'
+            ) AS "tags!"
+            FROM challenges c
+            JOIN categories cat ON cat.id = c.category_id
+            LEFT JOIN challenge_groups g ON g.id = c.group_id
+            ORDER BY c.id
+        "#
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let teams = sqlx::query_as!(
+        ArchiveTeam,
+        "SELECT public_id, name, email, division, created_at, extra_data FROM teams ORDER BY id"
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let users = sqlx::query_as!(
+        ArchiveUser,
+        r#"SELECT u.public_id, u.name, u.email, t.public_id AS "team?", u.captain, u.joined_at,
+            u.created_at
+        FROM users u
+            LEFT JOIN teams t ON t.id = u.team_id
+        ORDER BY u.id"#
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let submissions = sqlx::query_as!(
+        ArchiveSubmission,
+        r#"SELECT t.public_id AS team, u.public_id AS "user?", c.public_id AS challenge, s.submission,
+            s.is_correct, s.created_at
+        FROM submissions s
+            JOIN teams t ON t.id = s.team_id
+            JOIN challenges c ON c.id = s.challenge_id
+            LEFT JOIN users u ON u.id = s.user_id
+        ORDER BY s.id"#
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let deployments = sqlx::query_as!(
+        ArchiveDeployment,
+        r#"SELECT d.public_id, t.public_id AS "team?", c.public_id AS challenge, d.data, d.created_at,
+            d.expired_at, d.destroyed_at
+        FROM challenge_deployments d
+            JOIN challenges c ON c.id = d.challenge_id
+            LEFT JOIN teams t ON t.id = d.team_id
+        ORDER BY d.id"#
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let mut archive = Archive {
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now().naive_utc(),
+        event_toml,
+        categories,
+        groups,
+        challenges,
+        teams,
+        users,
+        submissions,
+        deployments,
+    };
+    if query.anonymize {
+        anonymize(&mut archive);
+    }
+
+    Ok(Json(archive))
+}
+
+/// Replaces everything that could tie teams and users back to real people. Public ids show up in
+/// leaderboard and profile urls, so they get fresh ones too, consistently across the archive.
+fn anonymize(archive: &mut Archive) {
+    let team_ids: HashMap<String, String> = archive
+        .teams
+        .iter()
+        .map(|t| (t.public_id.clone(), nanoid!()))
+        .collect();
+    let user_ids: HashMap<String, String> = archive
+        .users
+        .iter()
+        .map(|u| (u.public_id.clone(), nanoid!()))
+        .collect();
+    let remap = |ids: &HashMap<String, String>, id: &mut String| {
+        if let Some(new) = ids.get(id) {
+            *id = new.clone();
+        }
+    };
+
+    for (i, team) in archive.teams.iter_mut().enumerate() {
+        remap(&team_ids, &mut team.public_id);
+        team.name = format!("Team {}", i + 1);
+        team.email = format!("team{}@anonymized.invalid", i + 1);
+        team.extra_data = None;
+    }
+    for (i, user) in archive.users.iter_mut().enumerate() {
+        remap(&user_ids, &mut user.public_id);
+        user.team.iter_mut().for_each(|t| remap(&team_ids, t));
+        user.name = format!("User {}", i + 1);
+        user.email = format!("user{}@anonymized.invalid", i + 1);
+    }
+    for submission in &mut archive.submissions {
+        remap(&team_ids, &mut submission.team);
+        submission.user.iter_mut().for_each(|u| remap(&user_ids, u));
+        if !submission.is_correct {
+            submission.submission.clear();
+        }
+    }
+    for deployment in &mut archive.deployments {
+        deployment.team.iter_mut().for_each(|t| remap(&team_ids, t));
+    }
+}
+
+#[derive(Serialize)]
+struct ImportResult {
+    challenges: usize,
+    teams: u64,
+    users: u64,
+    submissions: u64,
+    deployments: u64,
+}
+
+/// Restores an archive into a database without teams, users or challenges. The event.toml in it isn't
+/// applied, the server has to be started with it separately. Deployments come back destroyed,
+/// their containers don't exist here.
+async fn import_archive(
+    StateE(state): StateE<State>,
+    _: Admin,
+    Json(archive): Json<Archive>,
+) -> Result<Json<ImportResult>> {
+    if archive.version != ARCHIVE_VERSION {
+        return Err(Error::ArchiveVersion(archive.version));
+    }
+
+    let mut tx = state.db.begin().await?;
+
+    let empty = sqlx::query_scalar!(
+        r#"SELECT NOT EXISTS(SELECT 1 FROM teams) AND NOT EXISTS(SELECT 1 FROM challenges)
+            AND NOT EXISTS(SELECT 1 FROM users) AS "empty!""#
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if !empty {
+        return Err(Error::NotEmpty);
+    }
+
+    sqlx::query!(
+        "INSERT INTO categories (name) SELECT n FROM unnest($1::text[]) n WHERE n NOT IN (SELECT name FROM categories)",
+        &archive.categories
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO challenge_groups (name) SELECT n FROM unnest($1::text[]) n WHERE n NOT IN (SELECT name FROM challenge_groups)",
+        &archive.groups
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let mut chall_ids = vec![];
+    for c in &archive.challenges {
+        let id = sqlx::query_scalar!(
+            "INSERT INTO challenges (
+                public_id, name, author, description, points_min, points_max, points_decay, flag,
+                attachments, visible, strategy, category_id, group_id, difficulty, release_at,
+                release_announced
+            ) VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11,
+                (SELECT id FROM categories WHERE name = $12),
+                (SELECT id FROM challenge_groups WHERE name = $13),
+                $14, $15, $16
+            ) RETURNING id",
+            c.public_id,
+            c.name,
+            c.author,
+            c.description,
+            c.points_min,
+            c.points_max,
+            c.points_decay,
+            c.flag,
+            c.attachments,
+            c.visible,
+            c.strategy as DeploymentStrategy,
+            c.category,
+            c.group,
+            c.difficulty,
+            c.release_at,
+            c.release_announced,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO tags (name) SELECT unnest($1::text[]) ON CONFLICT DO NOTHING",
+            &c.tags
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO challenge_tags (challenge_id, tag_id) SELECT $1, id FROM tags WHERE name = ANY($2)",
+            id,
+            &c.tags
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        chall_ids.push(id);
+    }
+
+    let teams = sqlx::query!(
+        "INSERT INTO teams (public_id, name, email, division, created_at, extra_data)
+        SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::timestamp[], $6::jsonb[])",
+        &archive.teams.iter().map(|t| t.public_id.clone()).collect::<Vec<_>>(),
+        &archive.teams.iter().map(|t| t.name.clone()).collect::<Vec<_>>(),
+        &archive.teams.iter().map(|t| t.email.clone()).collect::<Vec<_>>(),
+        &archive.teams.iter().map(|t| t.division.clone()).collect::<Vec<_>>() as &[Option<String>],
+        &archive.teams.iter().map(|t| t.created_at).collect::<Vec<_>>(),
+        &archive.teams.iter().map(|t| t.extra_data.clone()).collect::<Vec<_>>() as &[Option<Value>],
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    let users = sqlx::query!(
+        "INSERT INTO users (public_id, name, email, team_id, captain, joined_at, created_at)
+        SELECT u.public_id, u.name, u.email, t.id, u.captain, u.joined_at, u.created_at
+        FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::bool[], $6::timestamp[], $7::timestamp[])
+            AS u(public_id, name, email, team, captain, joined_at, created_at)
+            LEFT JOIN teams t ON t.public_id = u.team",
+        &archive.users.iter().map(|u| u.public_id.clone()).collect::<Vec<_>>(),
+        &archive.users.iter().map(|u| u.name.clone()).collect::<Vec<_>>(),
+        &archive.users.iter().map(|u| u.email.clone()).collect::<Vec<_>>(),
+        &archive.users.iter().map(|u| u.team.clone()).collect::<Vec<_>>() as &[Option<String>],
+        &archive.users.iter().map(|u| u.captain).collect::<Vec<_>>(),
+        &archive.users.iter().map(|u| u.joined_at).collect::<Vec<_>>() as &[Option<NaiveDateTime>],
+        &archive.users.iter().map(|u| u.created_at).collect::<Vec<_>>(),
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    let submissions = sqlx::query!(
+        "INSERT INTO submissions (submission, is_correct, team_id, challenge_id, created_at, user_id)
+        SELECT s.submission, s.is_correct, t.id, c.id, s.created_at, u.id
+        FROM UNNEST($1::text[], $2::bool[], $3::text[], $4::text[], $5::timestamp[], $6::text[])
+            AS s(submission, is_correct, team, challenge, created_at, usr)
+            JOIN teams t ON t.public_id = s.team
+            JOIN challenges c ON c.public_id = s.challenge
+            LEFT JOIN users u ON u.public_id = s.usr",
+        &archive
+            .submissions
+            .iter()
+            .map(|s| s.submission.clone())
+            .collect::<Vec<_>>(),
+        &archive
+            .submissions
+            .iter()
+            .map(|s| s.is_correct)
+            .collect::<Vec<_>>(),
+        &archive
+            .submissions
+            .iter()
+            .map(|s| s.team.clone())
+            .collect::<Vec<_>>(),
+        &archive
+            .submissions
+            .iter()
+            .map(|s| s.challenge.clone())
+            .collect::<Vec<_>>(),
+        &archive
+            .submissions
+            .iter()
+            .map(|s| s.created_at)
+            .collect::<Vec<_>>(),
+        &archive
+            .submissions
+            .iter()
+            .map(|s| s.user.clone())
+            .collect::<Vec<_>>() as &[Option<String>],
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    let deployments = sqlx::query!(
+        "INSERT INTO challenge_deployments (
+            public_id, team_id, challenge_id, deployed, data, created_at, expired_at, destroyed_at
+        )
+        SELECT d.public_id, t.id, c.id, false, d.data, d.created_at, d.expired_at,
+            COALESCE(d.destroyed_at, NOW())
+        FROM UNNEST($1::text[], $2::text[], $3::text[], $4::jsonb[], $5::timestamp[], $6::timestamp[], $7::timestamp[])
+            AS d(public_id, team, challenge, data, created_at, expired_at, destroyed_at)
+            JOIN challenges c ON c.public_id = d.challenge
+            LEFT JOIN teams t ON t.public_id = d.team",
+        &archive.deployments.iter().map(|d| d.public_id.clone()).collect::<Vec<_>>(),
+        &archive.deployments.iter().map(|d| d.team.clone()).collect::<Vec<_>>() as &[Option<String>],
+        &archive.deployments.iter().map(|d| d.challenge.clone()).collect::<Vec<_>>(),
+        &archive.deployments.iter().map(|d| d.data.clone()).collect::<Vec<_>>() as &[Option<Value>],
+        &archive.deployments.iter().map(|d| d.created_at).collect::<Vec<_>>(),
+        &archive.deployments.iter().map(|d| d.expired_at).collect::<Vec<_>>() as &[Option<NaiveDateTime>],
+        &archive.deployments.iter().map(|d| d.destroyed_at).collect::<Vec<_>>() as &[Option<NaiveDateTime>],
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    // points and leaderboard totals with this server's scoring config
+    for &id in &chall_ids {
+        update_chall_cache_tx(&mut tx, &state.event.scoring, id).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(ImportResult {
+        challenges: chall_ids.len(),
+        teams,
+        users,
+        submissions,
+        deployments,
+    }))
+}
+
+pub fn router() -> Router<crate::State> {
+    Router::new().route(
+        "/",
+        // archives of big events are well past the default limit
+        get(export_archive)
+            .post(import_archive)
+            .layer(DefaultBodyLimit::disable()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive() -> Archive {
+        let at = NaiveDateTime::default();
+        Archive {
+            version: ARCHIVE_VERSION,
+            exported_at: at,
+            event_toml: String::new(),
+            categories: vec![],
+            groups: vec![],
+            challenges: vec![],
+            teams: ["teamid-alpha", "teamid-beta"]
+                .map(|id| ArchiveTeam {
+                    public_id: id.to_owned(),
+                    name: format!("name-of-{id}"),
+                    email: format!("{id}@real.example"),
+                    division: None,
+                    created_at: at,
+                    extra_data: Some(serde_json::json!({ "school": "secret-school" })),
+                })
+                .into(),
+            users: vec![ArchiveUser {
+                public_id: "userid-carol".to_owned(),
+                name: "name-of-carol".to_owned(),
+                email: "carol@real.example".to_owned(),
+                team: Some("teamid-alpha".to_owned()),
+                captain: true,
+                joined_at: Some(at),
+                created_at: at,
+            }],
+            submissions: vec![
+                ArchiveSubmission {
+                    team: "teamid-alpha".to_owned(),
+                    user: Some("userid-carol".to_owned()),
+                    challenge: "chall".to_owned(),
+                    submission: "flag{right}".to_owned(),
+                    is_correct: true,
+                    created_at: at,
+                },
+                ArchiveSubmission {
+                    team: "teamid-beta".to_owned(),
+                    user: None,
+                    challenge: "chall".to_owned(),
+                    submission: "wrong-guess-by-beta".to_owned(),
+                    is_correct: false,
+                    created_at: at,
+                },
+            ],
+            deployments: vec![ArchiveDeployment {
+                public_id: "deployment".to_owned(),
+                team: Some("teamid-beta".to_owned()),
+                challenge: "chall".to_owned(),
+                data: None,
+                created_at: at,
+                expired_at: None,
+                destroyed_at: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn anonymize_removes_identities() {
+        let mut archive = archive();
+        anonymize(&mut archive);
+
+        let json = serde_json::to_string(&archive).unwrap();
+        for original in [
+            "teamid-alpha",
+            "teamid-beta",
+            "userid-carol",
+            "name-of",
+            "real.example",
+            "secret-school",
+            "wrong-guess-by-beta",
+        ] {
+            assert!(!json.contains(original), "{original} survived");
+        }
+    }
+
+    #[test]
+    fn anonymize_keeps_references() {
+        let mut archive = archive();
+        anonymize(&mut archive);
+
+        let alpha = &archive.teams[0].public_id;
+        let beta = &archive.teams[1].public_id;
+        assert_ne!(alpha, beta);
+        assert_eq!(archive.users[0].team.as_ref(), Some(alpha));
+        assert_eq!(&archive.submissions[0].team, alpha);
+        assert_eq!(
+            archive.submissions[0].user.as_ref(),
+            Some(&archive.users[0].public_id)
+        );
+        assert_eq!(&archive.submissions[1].team, beta);
+        assert_eq!(archive.deployments[0].team.as_ref(), Some(beta));
+        assert_eq!(archive.submissions[0].submission, "flag{right}");
+    }
+}
@@ -0,0 +1,57 @@
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, State as StateE},
+    routing::get,
+    Json, Router,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{attachments::attachment_path, extractors::Admin, Error, Result, State};
+
+#[derive(Serialize)]
+struct StoredAttachment {
+    sha256: String,
+    size: u64,
+}
+
+async fn get_attachment(
+    StateE(state): StateE<State>,
+    _: Admin,
+    Path(key): Path<String>,
+) -> Result<Json<StoredAttachment>> {
+    let path = attachment_path(&state.config.attachments_dir, &key)?;
+    let data = match tokio::fs::read(&path).await {
+        Ok(d) => d,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(Error::NotFoundAttachment)
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(Json(StoredAttachment {
+        sha256: format!("{:x}", Sha256::digest(&data)),
+        size: data.len() as u64,
+    }))
+}
+
+async fn put_attachment(
+    StateE(state): StateE<State>,
+    _: Admin,
+    Path(key): Path<String>,
+    data: Bytes,
+) -> Result<()> {
+    let path = attachment_path(&state.config.attachments_dir, &key)?;
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(&path, data).await?;
+
+    Ok(())
+}
+
+pub fn router() -> Router<crate::State> {
+    Router::new()
+        .route("/{*key}", get(get_attachment).put(put_attachment))
+        .layer(DefaultBodyLimit::disable())
+}
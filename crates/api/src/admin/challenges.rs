@@ -1,12 +1,12 @@
 use std::{collections::HashMap, str::FromStr};
 
 use crate::{
-    db::{update_chall_cache, DeploymentStrategy},
+    db::{update_chall_cache, update_chall_cache_tx, DeploymentStrategy},
     extractors::Admin,
-    Result, State,
+    Error, Result, State,
 };
 use axum::{
-    extract::State as StateE,
+    extract::{Query, State as StateE},
     routing::{delete, get, patch, post},
     Json, Router,
 };
@@ -15,7 +15,7 @@ use deployer_common::challenge::Challenge as DeployerChallenge;
 use eyre::eyre;
 use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgRow, FromRow, Row};
+use sqlx::{postgres::PgRow, FromRow, PgTransaction, Row};
 
 impl FromStr for DeploymentStrategy {
     type Err = eyre::Error;
@@ -37,10 +37,14 @@ pub struct Challenge {
     pub description: String,
     pub points_min: i32,
     pub points_max: i32,
+    pub points_decay: Option<f64>,
     pub flag: String,
     pub attachments: serde_json::Value,
     pub strategy: DeploymentStrategy,
     pub visible: bool,
+    pub difficulty: Option<String>,
+    pub tags: Vec<String>,
+    pub release_at: Option<NaiveDateTime>,
 
     pub category: Category,
     pub group: Option<ChallengeGroup>,
@@ -56,11 +60,15 @@ impl FromRow<'_, PgRow> for Challenge {
             description: row.try_get("description")?,
             points_min: row.try_get("points_min")?,
             points_max: row.try_get("points_max")?,
+            points_decay: row.try_get("points_decay")?,
             flag: row.try_get("flag")?,
             attachments: row.try_get("attachments")?,
             strategy: DeploymentStrategy::from_str(row.try_get("strategy")?)
                 .unwrap_or(DeploymentStrategy::Static),
             visible: row.try_get("visible")?,
+            difficulty: row.try_get("difficulty")?,
+            tags: row.try_get("tags")?,
+            release_at: row.try_get("release_at")?,
             category: Category {
                 id: row.try_get("category_id")?,
                 name: row.try_get("category_name")?,
@@ -98,10 +106,17 @@ async fn get_challenges(StateE(state): StateE<State>, _: Admin) -> Result<Json<V
                 m.description,
                 m.points_min,
                 m.points_max,
+                m.points_decay,
                 m.flag,
                 m.attachments,
                 m.strategy,
                 m.visible,
+                m.difficulty,
+                m.release_at,
+                COALESCE(
+                    (SELECT array_agg(t.name ORDER BY t.name) FROM challenge_tags ct JOIN tags t ON t.id = ct.tag_id WHERE ct.challenge_id = m.id),
+                    '{}'
+                ) AS tags,
                 c.id AS category_id,
                 c.name AS category_name,
                 g.id AS group_id,
@@ -125,22 +140,56 @@ pub struct UpsertChallenge {
     pub description: String,
     pub points_min: i32,
     pub points_max: i32,
+    #[serde(default)]
+    pub points_decay: Option<f64>,
     pub flag: String,
     pub attachments: serde_json::Value,
     pub strategy: DeploymentStrategy,
     pub visible: bool,
+    #[serde(default)]
+    pub difficulty: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub release_at: Option<NaiveDateTime>,
 
     pub category_id: i32,
     pub group_id: Option<i32>,
 }
 
+async fn set_tags(tx: &mut PgTransaction<'_>, chall_id: i32, tags: &[String]) -> Result<()> {
+    sqlx::query!(
+        "INSERT INTO tags (name) SELECT unnest($1::text[]) ON CONFLICT DO NOTHING",
+        tags
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM challenge_tags WHERE challenge_id = $1",
+        chall_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO challenge_tags (challenge_id, tag_id) SELECT $1, id FROM tags WHERE name = ANY($2)",
+        chall_id,
+        tags
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
 async fn upsert_challenge(
     StateE(state): StateE<State>,
     _: Admin,
     Json(payload): Json<UpsertChallenge>,
 ) -> Result<Json<Challenge>> {
+    let mut tx = state.db.begin().await?;
+
     // sqlx query macro cannot understand the custom challenge fromRow
-    let chall: Challenge = sqlx::query_as(
+    let mut chall: Challenge = sqlx::query_as(
         "WITH merged AS (
             INSERT INTO challenges (
                 public_id,
@@ -154,8 +203,11 @@ async fn upsert_challenge(
                 visible,
                 category_id,
                 group_id,
-                strategy
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12::deployment_strategy) 
+                strategy,
+                difficulty,
+                release_at,
+                points_decay
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12::deployment_strategy, $13, $14, $15) 
             ON CONFLICT(public_id) DO UPDATE 
             SET 
                 name = $2,
@@ -168,7 +220,11 @@ async fn upsert_challenge(
                 visible = $9,
                 category_id = $10,
                 group_id = $11,
-                strategy = $12::deployment_strategy
+                strategy = $12::deployment_strategy,
+                difficulty = $13,
+                release_at = $14,
+                points_decay = $15,
+                release_announced = challenges.release_announced AND challenges.release_at IS NOT DISTINCT FROM $14
                 RETURNING *
             )
             SELECT 
@@ -179,10 +235,17 @@ async fn upsert_challenge(
                 m.description,
                 m.points_min,
                 m.points_max,
+                m.points_decay,
                 m.flag,
                 m.attachments,
                 m.strategy::text,
                 m.visible,
+                m.difficulty,
+                m.release_at,
+                COALESCE(
+                    (SELECT array_agg(t.name ORDER BY t.name) FROM challenge_tags ct JOIN tags t ON t.id = ct.tag_id WHERE ct.challenge_id = m.id),
+                    '{}'
+                ) AS tags,
                 c.id AS category_id,
                 c.name AS category_name,
                 g.id AS group_id,
@@ -207,10 +270,21 @@ async fn upsert_challenge(
         DeploymentStrategy::Static => "static",
         DeploymentStrategy::Instanced => "instanced",
     })
-    .fetch_one(&state.db)
+    .bind(payload.difficulty)
+    .bind(payload.release_at)
+    .bind(payload.points_decay)
+    .fetch_one(&mut *tx)
     .await?;
 
-    update_chall_cache(&state.db, chall.id).await?;
+    // the query above returns the old tags
+    let mut tags = payload.tags;
+    tags.sort();
+    tags.dedup();
+    set_tags(&mut tx, chall.id, &tags).await?;
+    chall.tags = tags;
+
+    update_chall_cache_tx(&mut tx, &state.event.scoring, chall.id).await?;
+    tx.commit().await?;
 
     Ok(Json(chall))
 }
@@ -232,6 +306,61 @@ async fn delete_challenge(
     Ok(())
 }
 
+#[derive(Serialize)]
+struct PruneResult {
+    // false if the challenge was only hidden
+    deleted: bool,
+}
+
+/// Deletes a challenge that nobody has solved and that has no live deployments, otherwise hides it.
+async fn prune_challenge(
+    StateE(state): StateE<State>,
+    _: Admin,
+    Json(payload): Json<DeleteChallenge>,
+) -> Result<Json<PruneResult>> {
+    let mut tx = state.db.begin().await?;
+
+    let chall = sqlx::query!(
+        r#"SELECT
+            id,
+            EXISTS(SELECT 1 FROM submissions s WHERE s.challenge_id = c.id AND s.is_correct) AS "solved!",
+            EXISTS(SELECT 1 FROM challenge_deployments d WHERE d.challenge_id = c.id AND d.destroyed_at IS NULL) AS "deployed!"
+        FROM challenges c WHERE public_id = $1 FOR UPDATE"#,
+        payload.id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::NotFoundChallenge)?;
+
+    let deleted = !chall.solved && !chall.deployed;
+    if deleted {
+        // only wrong submissions and destroyed deployments are left at this point
+        sqlx::query!("DELETE FROM submissions WHERE challenge_id = $1", chall.id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(
+            "DELETE FROM challenge_deployments WHERE challenge_id = $1",
+            chall.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM challenges WHERE id = $1", chall.id)
+            .execute(&mut *tx)
+            .await?;
+    } else {
+        sqlx::query!(
+            "UPDATE challenges SET visible = false WHERE id = $1",
+            chall.id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(PruneResult { deleted }))
+}
+
 #[derive(Deserialize)]
 struct CreateCategory {
     name: String,
@@ -261,6 +390,35 @@ async fn list_categories(StateE(state): StateE<State>, _: Admin) -> Result<Json<
     ))
 }
 
+#[derive(Deserialize)]
+struct CreateGroup {
+    name: String,
+}
+
+async fn create_group(
+    StateE(state): StateE<State>,
+    _: Admin,
+    Json(payload): Json<CreateGroup>,
+) -> Result<Json<ChallengeGroup>> {
+    Ok(Json(
+        sqlx::query_as!(
+            ChallengeGroup,
+            "INSERT INTO challenge_groups (name) VALUES ($1) RETURNING *",
+            payload.name
+        )
+        .fetch_one(&state.db)
+        .await?,
+    ))
+}
+
+async fn list_groups(StateE(state): StateE<State>, _: Admin) -> Result<Json<Vec<ChallengeGroup>>> {
+    Ok(Json(
+        sqlx::query_as!(ChallengeGroup, "SELECT * FROM challenge_groups")
+            .fetch_all(&state.db)
+            .await?,
+    ))
+}
+
 #[derive(Serialize)]
 struct ChallengeDeploymentReq {
     challenge_id: i32,
@@ -360,9 +518,17 @@ async fn reload_deployer(StateE(state): StateE<State>, _: Admin) -> Result<()> {
     Ok(())
 }
 
+#[derive(Deserialize, Serialize)]
+struct LoadDeployerQuery {
+    // destroy deployments of challenges that aren't being loaded
+    #[serde(default)]
+    prune: bool,
+}
+
 async fn load_deployer(
     StateE(state): StateE<State>,
     _: Admin,
+    Query(query): Query<LoadDeployerQuery>,
     Json(challs): Json<HashMap<String, DeployerChallenge>>,
 ) -> Result<()> {
     let client = reqwest::Client::new();
@@ -372,13 +538,98 @@ async fn load_deployer(
             "{}/api/challenges/load",
             state.config.deployer_base
         ))
+        .query(&query)
         .json(&challs)
         .send()
-        .await?;
+        .await?
+        .error_for_status()?;
 
     Ok(())
 }
 
+#[derive(Deserialize)]
+struct VerifyChallenge {
+    id: String,
+}
+
+#[derive(Serialize)]
+struct ChallengeVerifyReq {
+    challenge_id: i32,
+}
+
+async fn verify_challenge(
+    StateE(state): StateE<State>,
+    _: Admin,
+    Json(payload): Json<VerifyChallenge>,
+) -> Result<Json<serde_json::Value>> {
+    let chall = sqlx::query!("SELECT id FROM challenges WHERE public_id = $1", payload.id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or(Error::NotFoundChallenge)?;
+
+    let check = reqwest::Client::new()
+        .post(format!(
+            "{}/api/challenge/verify",
+            state.config.deployer_base
+        ))
+        .json(&ChallengeVerifyReq {
+            challenge_id: chall.id,
+        })
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(Json(check))
+}
+
+#[derive(Deserialize)]
+struct SolveChecksQuery {
+    id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SolveCheck {
+    id: i32,
+    challenge_id: String,
+    periodic: bool,
+    // null while running
+    passed: Option<bool>,
+    exit_code: Option<i64>,
+    output: Option<String>,
+    created_at: NaiveDateTime,
+    finished_at: Option<NaiveDateTime>,
+}
+
+async fn list_solve_checks(
+    StateE(state): StateE<State>,
+    _: Admin,
+    Query(query): Query<SolveChecksQuery>,
+) -> Result<Json<Vec<SolveCheck>>> {
+    Ok(Json(
+        sqlx::query_as!(
+            SolveCheck,
+            "SELECT
+                s.id,
+                c.public_id AS challenge_id,
+                s.periodic,
+                s.passed,
+                s.exit_code,
+                s.output,
+                s.created_at,
+                s.finished_at
+            FROM solve_checks s JOIN challenges c ON c.id = s.challenge_id
+            WHERE $1::text IS NULL OR c.public_id = $1
+            ORDER BY s.created_at DESC
+            LIMIT 200",
+            query.id,
+        )
+        .fetch_all(&state.db)
+        .await?,
+    ))
+}
+
 async fn reap(StateE(state): StateE<State>, _: Admin) -> Result<Json<String>> {
     let containers = sqlx::query!("SELECT challenge_id, team_id FROM challenge_deployments WHERE NOW() > expired_at AND destroyed_at IS NULL").fetch_all(&state.db).await?;
     let client = reqwest::Client::new();
@@ -411,13 +662,13 @@ async fn update_cache_handler(
     Json(payload): Json<UpdateCachePayload>,
 ) -> Result<Json<String>> {
     if let Some(chall_id) = payload.id {
-        update_chall_cache(&state.db, chall_id).await?;
+        update_chall_cache(&state.db, &state.event.scoring, chall_id).await?;
     } else {
         let all_chall_ids: Vec<(i32,)> = sqlx::query_as("SELECT id FROM challenges")
             .fetch_all(&state.db)
             .await?;
         for (chall_id,) in all_chall_ids {
-            update_chall_cache(&state.db, chall_id).await?;
+            update_chall_cache(&state.db, &state.event.scoring, chall_id).await?;
         }
     }
     Ok(Json("Cache updated".to_string()))
@@ -428,12 +679,82 @@ pub fn router() -> Router<crate::State> {
         .route("/", get(get_challenges))
         .route("/", delete(delete_challenge))
         .route("/", patch(upsert_challenge))
+        .route("/prune", post(prune_challenge))
         .route("/category", get(list_categories))
         .route("/category", post(create_category))
+        .route("/group", get(list_groups))
+        .route("/group", post(create_group))
         .route("/deploy_static", post(deploy_static))
         .route("/destroy_static", post(destroy_static))
         .route("/reload_deployer", post(reload_deployer))
         .route("/load_deployer", post(load_deployer))
         .route("/reap", delete(reap))
+        .route("/verify", post(verify_challenge))
+        .route("/checks", get(list_solve_checks))
         .route("/update_cache", post(update_cache_handler))
 }
+
+// needs DATABASE_URL to point at a postgres server, every test gets its own database
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::StateInner, DB};
+    use serde_json::json;
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn groups(db: DB) {
+        let state = StateInner::for_tests(db);
+        let Json(category) = create_category(
+            StateE(state.clone()),
+            Admin,
+            Json(CreateCategory {
+                name: "web".to_owned(),
+            }),
+        )
+        .await
+        .unwrap();
+        let create = |name: &str| {
+            create_group(
+                StateE(state.clone()),
+                Admin,
+                Json(CreateGroup {
+                    name: name.to_owned(),
+                }),
+            )
+        };
+        let Json(series) = create("series").await.unwrap();
+        // the cli looks groups up by name, so they have to be unique
+        assert!(create("series").await.is_err());
+
+        let Json(groups) = list_groups(StateE(state.clone()), Admin).await.unwrap();
+        let groups: Vec<_> = groups.iter().map(|g| (g.id, g.name.as_str())).collect();
+        assert_eq!(groups, [(series.id, "series")]);
+
+        let upsert = |group_id: Option<i32>| {
+            let payload = json!({
+                "id": "part-1",
+                "name": "part 1",
+                "author": "author",
+                "description": "",
+                "points_min": 100,
+                "points_max": 500,
+                "flag": "flag{part-1}",
+                "attachments": {},
+                "strategy": "static",
+                "visible": true,
+                "category_id": category.id,
+                "group_id": group_id,
+            });
+            upsert_challenge(
+                StateE(state.clone()),
+                Admin,
+                Json(serde_json::from_value(payload).unwrap()),
+            )
+        };
+        let Json(chall) = upsert(Some(series.id)).await.unwrap();
+        assert_eq!(chall.group.map(|g| g.name).as_deref(), Some("series"));
+        // pushing it again without a group takes it out of the series
+        let Json(chall) = upsert(None).await.unwrap();
+        assert!(chall.group.is_none());
+    }
+}
@@ -1,64 +1,263 @@
 // idk if this warrants an entire file/router but putting lb export in one of the existing ones felt weird
-use axum::{extract::State as StateE, routing::get, Json, Router};
-use serde::Serialize;
+use std::collections::HashMap;
 
-use crate::{db::update_chall_cache, extractors::Admin, Result, State};
+use axum::{
+    extract::{Query, State as StateE},
+    http::header,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::leaderboard::{divisions, standings},
+    extractors::Admin,
+    Result, State, DB,
+};
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    // comma separated, every team if missing
+    divisions: Option<String>,
+    // ctftime only, adds the tasks and per team taskStats
+    #[serde(default)]
+    tasks: bool,
+}
+
+impl ExportQuery {
+    fn divisions(&self, state: &State) -> Result<Option<Vec<String>>> {
+        divisions(state, None, self.divisions.as_deref())
+    }
+}
+
+struct ExportSolve {
+    team_public_id: String,
+    team: String,
+    division: Option<String>,
+    challenge_public_id: String,
+    challenge: String,
+    category: String,
+    created_at: NaiveDateTime,
+    points: i32,
+    bonus: i32,
+}
+
+/// Every solve by a team eligible for any of the divisions, oldest first.
+async fn solves(db: &DB, divisions: Option<&[String]>) -> Result<Vec<ExportSolve>> {
+    Ok(sqlx::query_as!(
+        ExportSolve,
+        r#"
+        SELECT t.public_id AS team_public_id, t.name AS team, t.division,
+            ch.public_id AS challenge_public_id, ch.name AS challenge, c.name AS category,
+            s.created_at AS "created_at!", s.points AS "points!", s.bonus AS "bonus!"
+            FROM scored_solves() s
+            JOIN teams t ON t.id = s.team_id
+            JOIN challenges ch ON ch.id = s.challenge_id
+            JOIN categories c ON c.id = ch.category_id
+            WHERE $1::text[] IS NULL OR t.id IN (SELECT team_id FROM compute_leaderboard($1))
+            ORDER BY s.created_at ASC
+        "#,
+        divisions
+    )
+    .fetch_all(db)
+    .await?)
+}
+
+#[derive(Serialize)]
+struct CTFtimeTaskStat {
+    points: i32,
+    time: i64,
+}
 
 #[derive(Serialize)]
 struct CTFtimeStanding {
     pos: i32,
     team: String,
     score: i32,
+    #[serde(rename = "taskStats", skip_serializing_if = "Option::is_none")]
+    task_stats: Option<HashMap<String, CTFtimeTaskStat>>,
+    #[serde(rename = "lastAccept", skip_serializing_if = "Option::is_none")]
+    last_accept: Option<i64>,
 }
 
 #[derive(Serialize)]
 struct CTFtimeLeaderboard {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tasks: Option<Vec<String>>,
     standings: Vec<CTFtimeStanding>,
 }
 
-#[derive(sqlx::FromRow)]
-struct ExportLeaderboardEntry {
-    name: String,
-    score: i32,
-    rank: i32,
-}
-
 async fn export_ctftime_leaderboard(
     StateE(state): StateE<State>,
     _: Admin,
+    Query(query): Query<ExportQuery>,
 ) -> Result<Json<CTFtimeLeaderboard>> {
-    let all_chall_ids: Vec<i32> = sqlx::query_scalar("SELECT id FROM challenges")
+    let divisions = query.divisions(&state)?;
+    let standings = standings(&state.db, divisions.as_deref()).await?;
+
+    // team public id to task to stats. challenge names are only unique within a category, so tasks
+    // are named category/challenge
+    let mut task_stats: HashMap<String, HashMap<String, CTFtimeTaskStat>> = HashMap::new();
+    let tasks = if query.tasks {
+        for solve in solves(&state.db, divisions.as_deref()).await? {
+            task_stats.entry(solve.team_public_id).or_default().insert(
+                format!("{}/{}", solve.category, solve.challenge),
+                CTFtimeTaskStat {
+                    points: solve.points + solve.bonus,
+                    time: solve.created_at.and_utc().timestamp(),
+                },
+            );
+        }
+        let tasks = sqlx::query_scalar!(
+            r#"SELECT c.name || '/' || ch.name AS "name!" FROM challenges ch
+            JOIN categories c ON c.id = ch.category_id
+            WHERE ch.visible OR ch.id IN (SELECT challenge_id FROM submissions WHERE is_correct = true)
+            ORDER BY ch.id"#
+        )
         .fetch_all(&state.db)
         .await?;
-    for chall_id in all_chall_ids {
-        update_chall_cache(&state.db, chall_id).await?;
-    }
-
-    let db_entries = sqlx::query_as!(
-        ExportLeaderboardEntry,
-        r#"
-        SELECT t.name, lb.score as "score!", lb.rank as "rank!"
-            FROM teams t
-            JOIN compute_leaderboard(NULL) lb ON lb.team_id = t.id
-            WHERE lb.score > 0
-            ORDER BY lb.rank ASC
-        "#
-    )
-    .fetch_all(&state.db)
-    .await?;
+        Some(tasks)
+    } else {
+        None
+    };
 
-    let standings: Vec<CTFtimeStanding> = db_entries
+    let standings: Vec<CTFtimeStanding> = standings
         .into_iter()
+        .filter(|entry| entry.score > 0)
         .map(|entry| CTFtimeStanding {
             pos: entry.rank,
+            task_stats: query
+                .tasks
+                .then(|| task_stats.remove(&entry.public_id).unwrap_or_default()),
+            last_accept: entry
+                .last_solve
+                .filter(|_| query.tasks)
+                .map(|t| t.and_utc().timestamp()),
             team: entry.name,
             score: entry.score,
         })
         .collect();
 
-    Ok(Json(CTFtimeLeaderboard { standings }))
+    Ok(Json(CTFtimeLeaderboard { tasks, standings }))
+}
+
+// spreadsheet apps run fields starting with these as formulas
+fn csv_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '@', '-']) && field.parse::<f64>().is_err() {
+        format!("'{field}")
+    } else {
+        field.to_owned()
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+fn csv_response(name: &str, columns: &[&str], rows: Vec<Vec<String>>) -> impl IntoResponse {
+    let mut csv = columns.join(",") + "\n";
+    for row in rows {
+        let fields: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+        csv += &fields.join(",");
+        csv.push('\n');
+    }
+
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv".to_owned()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{name}\""),
+            ),
+        ],
+        csv,
+    )
+}
+
+async fn export_standings_csv(
+    StateE(state): StateE<State>,
+    _: Admin,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse> {
+    let divisions = query.divisions(&state)?;
+    let rows = standings(&state.db, divisions.as_deref())
+        .await?
+        .into_iter()
+        .map(|s| {
+            vec![
+                s.rank.to_string(),
+                s.public_id,
+                s.name,
+                s.division.unwrap_or_default(),
+                s.score.to_string(),
+                s.solves.to_string(),
+                s.last_solve.map(|t| t.to_string()).unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    Ok(csv_response(
+        "standings.csv",
+        &[
+            "rank",
+            "team_id",
+            "team",
+            "division",
+            "score",
+            "solves",
+            "last_solve",
+        ],
+        rows,
+    ))
+}
+
+async fn export_solves_csv(
+    StateE(state): StateE<State>,
+    _: Admin,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse> {
+    let divisions = query.divisions(&state)?;
+    let rows = solves(&state.db, divisions.as_deref())
+        .await?
+        .into_iter()
+        .map(|s| {
+            vec![
+                s.created_at.to_string(),
+                s.team_public_id,
+                s.team,
+                s.division.unwrap_or_default(),
+                s.challenge_public_id,
+                s.challenge,
+                s.category,
+                s.points.to_string(),
+                s.bonus.to_string(),
+            ]
+        })
+        .collect();
+
+    Ok(csv_response(
+        "solves.csv",
+        &[
+            "time",
+            "team_id",
+            "team",
+            "division",
+            "challenge_id",
+            "challenge",
+            "category",
+            "points",
+            "bonus",
+        ],
+        rows,
+    ))
 }
 
 pub fn router() -> Router<crate::State> {
-    Router::new().route("/ctftime", get(export_ctftime_leaderboard))
+    Router::new()
+        .route("/ctftime", get(export_ctftime_leaderboard))
+        .route("/standings.csv", get(export_standings_csv))
+        .route("/solves.csv", get(export_solves_csv))
 }
@@ -1,4 +1,6 @@
 use axum::Router;
+mod archive;
+mod attachments;
 mod auth;
 mod challenges;
 mod export;
@@ -7,5 +9,7 @@ pub fn router() -> Router<crate::State> {
     Router::new()
         .nest("/challs", challenges::router())
         .nest("/auth", auth::router())
+        .nest("/attachments", attachments::router())
         .nest("/export", export::router())
+        .nest("/archive", archive::router())
 }
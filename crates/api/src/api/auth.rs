@@ -13,7 +13,7 @@ use validator::Validate;
 use crate::{
     email::PendingTeamVerification,
     extractors::Auth,
-    jwt::{decode_jwt, generate_jwt, Claims},
+    jwt::{decode_jwt, generate_jwt, generate_user_jwt, Claims},
     Result, State,
 };
 
@@ -114,7 +114,12 @@ async fn verify_email(
     let mut cookie = Cookie::new("token", jwt);
     cookie.set_path("/");
     cookie.set_max_age(time::Duration::days(30));
-    Ok((jar.add(cookie), Json(TeamId { id: team.public_id })))
+    Ok((
+        jar.add(cookie),
+        Json(TeamId {
+            id: Some(team.public_id),
+        }),
+    ))
 }
 
 #[derive(Serialize, Deserialize)]
@@ -135,6 +140,11 @@ pub enum VerificationDetailsResponse {
         new_email: String,
         r#type: String,
     },
+    UserRegistration {
+        name: String,
+        email: String,
+        r#type: String,
+    },
 }
 
 async fn get_verification_details(
@@ -164,6 +174,13 @@ async fn get_verification_details(
                 r#type: "email_update".to_string(),
             }))
         }
+        Some(crate::email::PendingVerification::User(details)) => {
+            Ok(Json(VerificationDetailsResponse::UserRegistration {
+                name: details.name,
+                email: details.email,
+                r#type: "user_registration".to_string(),
+            }))
+        }
         None => Err(crate::error::Error::InvalidToken),
     }
 }
@@ -173,18 +190,25 @@ struct Token {
     token: String,
 }
 
+// hands out a token for whoever is asking, users don't get the team token
 async fn gen_token(
     StateE(state): StateE<State>,
-    Auth(Claims { team_id, .. }): Auth,
+    Auth(Claims {
+        team_id, user_id, ..
+    }): Auth,
 ) -> Result<Json<Token>> {
-    let jwt = generate_jwt(&state.config.jwt_keys, &team_id, Duration::days(30))?;
+    let jwt = match user_id {
+        Some(user_id) => generate_user_jwt(&state.config.jwt_keys, &user_id, Duration::days(30))?,
+        None => generate_jwt(&state.config.jwt_keys, &team_id, Duration::days(30))?,
+    };
 
     return Ok(Json(Token { token: jwt }));
 }
 
 #[derive(Serialize)]
-struct TeamId {
-    id: String,
+pub(crate) struct TeamId {
+    // None for users that aren't in a team yet
+    pub(crate) id: Option<String>,
 }
 
 async fn login(
@@ -193,11 +217,15 @@ async fn login(
     Json(Token { token: jwt }): Json<Token>,
 ) -> Result<(CookieJar, Json<TeamId>)> {
     let claims = decode_jwt(&state.config.jwt_keys, &jwt)?;
+    let id = match claims.user_id {
+        Some(user_id) => super::users::team_of(&state.db, &user_id).await?,
+        None => Some(claims.team_id),
+    };
 
     let mut cookie = Cookie::new("token", jwt);
     cookie.set_path("/");
     cookie.set_max_age(time::Duration::days(30));
-    Ok((jar.add(cookie), Json(TeamId { id: claims.team_id })))
+    Ok((jar.add(cookie), Json(TeamId { id })))
 }
 
 async fn resend_token_handler(
@@ -1,8 +1,8 @@
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use crate::{db::update_chall_cache, extractors::Auth, Error, Result, State};
+use crate::{db::update_chall_cache_tx, extractors::Auth, Error, Result, State};
 use axum::{
-    extract::{Path, State as StateE},
+    extract::{Path, Query, State as StateE},
     routing::{delete, get, post},
     Json, Router,
 };
@@ -22,24 +22,52 @@ pub struct PublicChallenge {
     description: String,
     points: i32,
     solves: i32,
+    // name -> { url, sha256, size }, or name -> url for challenges pushed by older clis
     attachments: serde_json::Value,
     category: String,
+    // challenges in the same group are parts of a series
+    group: Option<String>,
+    tags: Vec<String>,
+    difficulty: Option<String>,
+    // only in the future for admins previewing unreleased challenges
+    release_at: Option<NaiveDateTime>,
     deployment_id: String,
     strategy: String,
     solved_at: Option<NaiveDateTime>,
 }
 
+#[derive(Deserialize)]
+pub struct ChallengeFilter {
+    category: Option<String>,
+    difficulty: Option<String>,
+    // comma separated, challenges need all of them
+    tags: Option<String>,
+    solved: Option<bool>,
+}
+
 // NOTE: All of the routes in this file are PUBLICALLY
 // ACCESSABLE!! Do not leak any important information.
 pub async fn list(
     StateE(state): StateE<State>,
     Auth(claims): Auth,
+    Query(filter): Query<ChallengeFilter>,
 ) -> Result<Json<Vec<PublicChallenge>>> {
     if !claims.ethereal() && Utc::now().naive_utc() < state.event.start_time {
         return Err(Error::EventNotStarted(state.event.start_time.clone()));
     }
 
+    let now = Utc::now().naive_utc();
     let solves = super::profile::get_solves(&state.db, &claims.team_id).await?;
+    let mut tags: Vec<String> = filter
+        .tags
+        .iter()
+        .flat_map(|t| t.split(','))
+        .map(|t| t.trim().to_owned())
+        .filter(|t| !t.is_empty())
+        .collect();
+    // the query counts matching tags, so duplicates would never match
+    tags.sort();
+    tags.dedup();
 
     // TODO cd.public_id is an unexpected null apparently, this is a sqlx bug
     let mut challs = sqlx::query_as!(
@@ -58,13 +86,32 @@ pub async fn list(
             strategy::text AS "strategy!",
             COALESCE(cd.public_id, '') AS "deployment_id!",
             categories.name AS category,
+            g.name AS "group?",
+            COALESCE(
+                (SELECT array_agg(t.name ORDER BY t.name) FROM challenge_tags ct JOIN tags t ON t.id = ct.tag_id WHERE ct.challenge_id = c.id),
+                '{}'
+            ) AS "tags!",
+            c.difficulty,
+            c.release_at,
             NULL::timestamp AS "solved_at"
         FROM challenges c JOIN categories ON categories.id = category_id
+        LEFT JOIN challenge_groups g ON g.id = c.group_id
         LEFT JOIN challenge_deployments cd ON destroyed_at IS NULL AND challenge_id = c.id AND (team_id IS NULL or team_id = (SELECT id FROM teams WHERE public_id = $1))
         WHERE visible IN (true, $2)
+            AND (NOT $2 OR c.release_at IS NULL OR c.release_at <= $6)
+            AND ($3::text IS NULL OR categories.name = $3)
+            AND ($4::text IS NULL OR c.difficulty = $4)
+            AND cardinality($5::text[]) = (
+                SELECT count(*) FROM challenge_tags ct JOIN tags t ON t.id = ct.tag_id
+                WHERE ct.challenge_id = c.id AND t.name = ANY($5)
+            )
         ORDER BY solves DESC"#,
         claims.team_id,
         !claims.ethereal(),
+        filter.category,
+        filter.difficulty,
+        &tags,
+        now,
     )
         .fetch_all(&state.db)
         .await?;
@@ -77,6 +124,9 @@ pub async fn list(
             }
         }
     }
+    if let Some(solved) = filter.solved {
+        challs.retain(|c| c.solved_at.is_some() == solved);
+    }
 
     Ok(Json(challs))
 }
@@ -86,6 +136,8 @@ pub struct ChallengeSolve {
     id: String,
     name: String,
     solved_at: NaiveDateTime,
+    // blood bonus points
+    bonus: i32,
 }
 
 pub async fn challenge_solves(
@@ -102,14 +154,17 @@ pub async fn challenge_solves(
         r#"SELECT 
             t.public_id AS id,
             t.name AS name,
-            s.created_at AS solved_at 
-        FROM submissions s
+            s.created_at AS "solved_at!",
+            s.bonus AS "bonus!"
+        FROM scored_solves() s
         JOIN teams t ON s.team_id = t.id
         JOIN challenges c ON s.challenge_id = c.id
         WHERE c.public_id = $1 
-        AND s.is_correct = true
-        ORDER BY solved_at ASC"#,
+        AND (NOT $2 OR c.release_at IS NULL OR c.release_at <= $3)
+        ORDER BY s.place ASC"#,
         chall_id,
+        !a.ethereal(),
+        Utc::now().naive_utc(),
     )
     .fetch_all(&state.db)
     .await?;
@@ -127,11 +182,61 @@ fn leet<R>(flag: String, bits: BitReader<R, LittleEndian>) -> String {
     "".to_string()
 }
 
+#[derive(Serialize)]
+pub struct SubmitResult {
+    // the challenge's points after this solve
+    points: i32,
+    // 1 for first blood, null for ethereal teams whose solves aren't recorded
+    place: Option<i32>,
+}
+
+async fn announce_first_blood(state: State, team_public_id: String, chall_public_id: String) {
+    let Some(url) = state.config.bloodbot_discord_webhook_url.as_ref() else {
+        return;
+    };
+
+    #[derive(Serialize)]
+    struct WebhookData {
+        content: String,
+        embeds: Option<()>,
+        attachments: Vec<()>,
+    }
+
+    let res = async {
+        let team = sqlx::query!(
+            "SELECT name FROM teams WHERE public_id = $1",
+            team_public_id
+        )
+        .fetch_one(&state.db)
+        .await?;
+        // TODO(aiden): make this hookable instead of just vomitting this code here
+        reqwest::Client::new()
+            .post(url)
+            .json(&WebhookData {
+                content: format!(
+                    "Congrats to `{}` for first blooding `{}`!",
+                    team.name, chall_public_id
+                ),
+                embeds: None,
+                attachments: Vec::new(),
+            })
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok::<_, Error>(())
+    }
+    .await;
+
+    if let Err(e) = res {
+        log::error!("Failed to announce first blood: {}", e);
+    }
+}
+
 pub async fn submit(
     StateE(state): StateE<State>,
     Auth(claims): Auth,
     Json(submission): Json<Submission>,
-) -> Result<()> {
+) -> Result<Json<SubmitResult>> {
     let now = Utc::now().naive_utc();
     if now < state.event.start_time {
         return Err(Error::EventNotStarted(state.event.start_time.clone()));
@@ -143,79 +248,99 @@ pub async fn submit(
     struct AnswerInfo {
         id: i32,
         flag: String,
-        solves: i32,
+        points: i32,
+        release_at: Option<NaiveDateTime>,
     }
 
     let answer_info: AnswerInfo = sqlx::query_as!(
         AnswerInfo,
-        "SELECT id, flag, c_solves AS solves FROM challenges WHERE public_id = $1",
+        "SELECT id, flag, c_points AS points, release_at FROM challenges WHERE public_id = $1",
         submission.challenge_id
     )
     .fetch_one(&state.db)
     .await?;
 
+    // unreleased challenges don't exist as far as players know
+    if !claims.ethereal() && answer_info.release_at.is_some_and(|r| now < r) {
+        return Err(Error::NotFoundChallenge);
+    }
+
     let is_correct = answer_info.flag == submission.flag;
 
-    if !claims.ethereal() {
+    if claims.ethereal() {
+        return if is_correct {
+            Ok(Json(SubmitResult {
+                points: answer_info.points,
+                place: None,
+            }))
+        } else {
+            Err(Error::WrongFlag)
+        };
+    }
+
+    if !is_correct {
         sqlx::query!(
-            r#"INSERT INTO submissions (submission, is_correct, team_id, challenge_id)
-            VALUES ($1, $2, (SELECT id FROM teams WHERE public_id = $3), $4)"#,
+            r#"INSERT INTO submissions (submission, is_correct, team_id, challenge_id, user_id)
+            VALUES ($1, false, (SELECT id FROM teams WHERE public_id = $2), $3,
+                (SELECT id FROM users WHERE public_id = $4))"#,
             submission.flag,
-            is_correct,
             claims.team_id,
             answer_info.id,
+            claims.user_id,
         )
         .execute(&state.db)
         .await?;
+        return Err(Error::WrongFlag);
     }
 
-    if is_correct {
-        if !claims.ethereal() {
-            update_chall_cache(&state.db, answer_info.id).await?;
-            if answer_info.solves == 0 {
-                if let Some(u) = state.config.bloodbot_discord_webhook_url.as_ref() {
-                    // TODO(aiden): make this hookable instead of just vomitting this code here
-                    let client = reqwest::Client::new();
-
-                    #[derive(Serialize)]
-                    struct WebhookData {
-                        content: String,
-                        embeds: Option<()>,
-                        attachments: Vec<()>,
-                    }
-                    let msg = format!(
-                        "Congrats to `{}` for first blooding `{}`!",
-                        sqlx::query!(
-                            "SELECT name FROM teams WHERE public_id = $1",
-                            claims.team_id
-                        )
-                        .fetch_one(&state.db)
-                        .await?
-                        .name,
-                        sqlx::query!(
-                            "SELECT public_id FROM challenges WHERE id = $1",
-                            answer_info.id
-                        )
-                        .fetch_one(&state.db)
-                        .await?
-                        .public_id
-                    );
-                    client
-                        .post(u)
-                        .json(&WebhookData {
-                            content: msg,
-                            embeds: None,
-                            attachments: Vec::new(),
-                        })
-                        .send()
-                        .await?;
-                }
-            }
-        }
-        Ok(())
-    } else {
-        Err(Error::WrongFlag)
+    // the solve, its place and the new points are all recorded under the challenge row lock
+    let mut tx = state.db.begin().await?;
+    sqlx::query!(
+        "SELECT id FROM challenges WHERE id = $1 FOR UPDATE",
+        answer_info.id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let inserted = sqlx::query!(
+        r#"INSERT INTO submissions (submission, is_correct, team_id, challenge_id, user_id)
+        VALUES ($1, true, (SELECT id FROM teams WHERE public_id = $2), $3,
+            (SELECT id FROM users WHERE public_id = $4))
+        ON CONFLICT (team_id, challenge_id) WHERE is_correct DO NOTHING
+        RETURNING id"#,
+        submission.flag,
+        claims.team_id,
+        answer_info.id,
+        claims.user_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    if inserted.is_none() {
+        return Err(Error::AlreadySolved);
     }
+
+    let place = sqlx::query_scalar!(
+        r#"SELECT count(*)::int AS "place!" FROM submissions WHERE challenge_id = $1 AND is_correct"#,
+        answer_info.id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    let points = update_chall_cache_tx(&mut tx, &state.event.scoring, answer_info.id).await?;
+
+    tx.commit().await?;
+
+    if place == 1 {
+        tokio::spawn(announce_first_blood(
+            state.clone(),
+            claims.team_id,
+            submission.challenge_id,
+        ));
+    }
+
+    Ok(Json(SubmitResult {
+        points,
+        place: Some(place),
+    }))
 }
 
 #[derive(Serialize)]
@@ -264,10 +389,12 @@ pub async fn deploy(
     let record = sqlx::query!(
         r#"SELECT teams.id AS team_id, challenges.id AS challenge_id, challenges.strategy::text AS "strategy!"
 FROM teams, challenges 
-WHERE teams.public_id = $1 AND challenges.public_id = $2 AND challenges.visible IN (true, $3);"#,
+WHERE teams.public_id = $1 AND challenges.public_id = $2 AND challenges.visible IN (true, $3)
+    AND (NOT $3 OR challenges.release_at IS NULL OR challenges.release_at <= $4);"#,
         claims.team_id,
         pub_id,
         !claims.ethereal(),
+        now,
     )
         .fetch_one(&state.db)
         .await
@@ -419,3 +546,270 @@ pub fn router() -> Router<crate::State> {
         .route("/solves/{chall_id}", get(challenge_solves))
         .route("/deploy/get/{deployment_id}", get(get_deployment))
 }
+
+// needs DATABASE_URL to point at a postgres server, every test gets its own database
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::StateInner, jwt::Claims, DB};
+    use chrono::TimeDelta;
+
+    fn claims(team_id: &str, ethereal: bool) -> Claims {
+        Claims {
+            exp: 0,
+            team_id: team_id.to_owned(),
+            ethereal: Some(ethereal),
+            user_id: None,
+        }
+    }
+
+    async fn team(db: &DB, name: &str) -> i32 {
+        sqlx::query_scalar(
+            "INSERT INTO teams (public_id, name, email) VALUES ($1, $1, $1 || '@example.com') RETURNING id",
+        )
+        .bind(name)
+        .fetch_one(db)
+        .await
+        .unwrap()
+    }
+
+    async fn chall(
+        db: &DB,
+        name: &str,
+        category: &str,
+        difficulty: Option<&str>,
+        tags: &[&str],
+    ) -> i32 {
+        sqlx::query("INSERT INTO categories (name) VALUES ($1) ON CONFLICT DO NOTHING")
+            .bind(category)
+            .execute(db)
+            .await
+            .unwrap();
+        let id = sqlx::query_scalar(
+            r#"INSERT INTO challenges (public_id, name, points_min, points_max, flag, attachments, category_id, author, visible, difficulty)
+            SELECT $1, $1, 100, 500, 'flag', '{}', id, 'author', true, $3 FROM categories WHERE name = $2 RETURNING id"#,
+        )
+        .bind(name)
+        .bind(category)
+        .bind(difficulty)
+        .fetch_one(db)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"WITH t AS (INSERT INTO tags (name) SELECT UNNEST($2::text[]) ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name RETURNING id)
+            INSERT INTO challenge_tags (challenge_id, tag_id) SELECT $1, id FROM t"#,
+        )
+        .bind(id)
+        .bind(tags)
+        .execute(db)
+        .await
+        .unwrap();
+        id
+    }
+
+    async fn solve(db: &DB, team: i32, chall: i32) {
+        sqlx::query(
+            "INSERT INTO submissions (submission, is_correct, team_id, challenge_id) VALUES ('flag', true, $1, $2)",
+        )
+        .bind(team)
+        .bind(chall)
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    async fn listed(state: &State, claims: Claims, filter: ChallengeFilter) -> Vec<String> {
+        let Json(challs) = list(StateE(state.clone()), Auth(claims), Query(filter))
+            .await
+            .unwrap();
+        let mut ids: Vec<_> = challs.into_iter().map(|c| c.public_id).collect();
+        ids.sort();
+        ids
+    }
+
+    fn filter(
+        category: Option<&str>,
+        difficulty: Option<&str>,
+        tags: Option<&str>,
+        solved: Option<bool>,
+    ) -> ChallengeFilter {
+        ChallengeFilter {
+            category: category.map(str::to_owned),
+            difficulty: difficulty.map(str::to_owned),
+            tags: tags.map(str::to_owned),
+            solved,
+        }
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn list_filters(db: DB) {
+        let a = team(&db, "a").await;
+        chall(&db, "xss", "web", Some("easy"), &["client", "js"]).await;
+        let sqli = chall(&db, "sqli", "web", Some("hard"), &["sql"]).await;
+        chall(&db, "rop", "pwn", Some("easy"), &[]).await;
+        solve(&db, a, sqli).await;
+        let state = StateInner::for_tests(db);
+        let list = |f| listed(&state, claims("a", false), f);
+
+        assert_eq!(
+            list(filter(None, None, None, None)).await,
+            ["rop", "sqli", "xss"]
+        );
+        assert_eq!(
+            list(filter(Some("web"), None, None, None)).await,
+            ["sqli", "xss"]
+        );
+        assert_eq!(
+            list(filter(None, Some("easy"), None, None)).await,
+            ["rop", "xss"]
+        );
+        assert_eq!(
+            list(filter(Some("web"), Some("easy"), None, None)).await,
+            ["xss"]
+        );
+        assert_eq!(list(filter(None, None, None, Some(true))).await, ["sqli"]);
+        assert_eq!(
+            list(filter(None, None, None, Some(false))).await,
+            ["rop", "xss"]
+        );
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn list_tag_filters(db: DB) {
+        team(&db, "a").await;
+        chall(&db, "xss", "web", None, &["client", "js"]).await;
+        chall(&db, "proto", "web", None, &["js"]).await;
+        chall(&db, "rop", "pwn", None, &[]).await;
+        let state = StateInner::for_tests(db);
+        let list = |tags| {
+            listed(
+                &state,
+                claims("a", false),
+                filter(None, None, Some(tags), None),
+            )
+        };
+
+        assert_eq!(list("js").await, ["proto", "xss"]);
+        // every tag has to match
+        assert_eq!(list("js,client").await, ["xss"]);
+        // whitespace, duplicates and empty entries are ignored
+        assert_eq!(list(" client , js,,js ").await, ["xss"]);
+        assert_eq!(list(",").await, ["proto", "rop", "xss"]);
+        assert!(list("js,heap").await.is_empty());
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn list_groups(db: DB) {
+        team(&db, "a").await;
+        let part = chall(&db, "part-1", "web", None, &[]).await;
+        chall(&db, "single", "web", None, &[]).await;
+        sqlx::query(
+            "WITH g AS (INSERT INTO challenge_groups (name) VALUES ('series') RETURNING id) UPDATE challenges SET group_id = (SELECT id FROM g) WHERE id = $1",
+        )
+        .bind(part)
+        .execute(&db)
+        .await
+        .unwrap();
+        let state = StateInner::for_tests(db);
+
+        let Json(challs) = list(
+            StateE(state),
+            Auth(claims("a", false)),
+            Query(filter(None, None, None, None)),
+        )
+        .await
+        .unwrap();
+        let mut groups: Vec<_> = challs
+            .iter()
+            .map(|c| (c.public_id.as_str(), c.group.as_deref()))
+            .collect();
+        groups.sort();
+        assert_eq!(groups, [("part-1", Some("series")), ("single", None)]);
+    }
+
+    async fn release(db: &DB, chall: i32, release_at: NaiveDateTime) {
+        sqlx::query("UPDATE challenges SET release_at = $2 WHERE id = $1")
+            .bind(chall)
+            .bind(release_at)
+            .execute(db)
+            .await
+            .unwrap();
+    }
+
+    async fn solvers(state: &State, claims: Claims, chall: &str) -> Vec<String> {
+        let Json(solves) =
+            challenge_solves(StateE(state.clone()), Auth(claims), Path(chall.to_owned()))
+                .await
+                .unwrap();
+        solves.into_iter().map(|s| s.id).collect()
+    }
+
+    async fn submit_flag(state: &State, claims: Claims, chall: &str) -> Result<Json<SubmitResult>> {
+        submit(
+            StateE(state.clone()),
+            Auth(claims),
+            Json(Submission {
+                flag: "flag".to_owned(),
+                challenge_id: chall.to_owned(),
+            }),
+        )
+        .await
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn release_gating(db: DB) {
+        team(&db, "a").await;
+        let b = team(&db, "b").await;
+        let now = Utc::now().naive_utc();
+        chall(&db, "always", "web", None, &[]).await;
+        let released = chall(&db, "released", "web", None, &[]).await;
+        let unreleased = chall(&db, "unreleased", "web", None, &[]).await;
+        release(&db, released, now - TimeDelta::hours(1)).await;
+        release(&db, unreleased, now + TimeDelta::hours(1)).await;
+        // e.g. from testing before the release time was set
+        solve(&db, b, unreleased).await;
+        let state = StateInner::for_tests(db);
+        let (player, admin) = (claims("a", false), claims("a", true));
+
+        assert_eq!(
+            listed(&state, player.clone(), filter(None, None, None, None)).await,
+            ["always", "released"]
+        );
+        assert_eq!(
+            listed(&state, admin.clone(), filter(None, None, None, None)).await,
+            ["always", "released", "unreleased"]
+        );
+
+        assert!(solvers(&state, player.clone(), "unreleased")
+            .await
+            .is_empty());
+        assert_eq!(solvers(&state, admin.clone(), "unreleased").await, ["b"]);
+
+        assert!(matches!(
+            submit_flag(&state, player.clone(), "unreleased").await,
+            Err(Error::NotFoundChallenge)
+        ));
+        assert!(matches!(
+            submit_flag(&state, player.clone(), "released").await,
+            Ok(Json(SubmitResult { place: Some(1), .. }))
+        ));
+        assert!(matches!(
+            submit_flag(&state, admin, "unreleased").await,
+            Ok(Json(SubmitResult { place: None, .. }))
+        ));
+
+        let deploy = |chall: &str| {
+            deploy(
+                StateE(state.clone()),
+                Auth(player.clone()),
+                Path(chall.to_owned()),
+            )
+        };
+        assert!(matches!(
+            deploy("unreleased").await,
+            Err(Error::NotFoundChallenge)
+        ));
+        // past the release check, static challenges just can't be deployed
+        assert!(matches!(deploy("released").await, Err(Error::GenericError)));
+    }
+}
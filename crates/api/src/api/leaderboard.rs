@@ -1,11 +1,12 @@
 use crate::{extractors::Auth, Error, Result, State, DB};
 use axum::{
-    extract::{Path, State as StateE},
+    extract::{Path, Query, State as StateE},
     routing::get,
     Json, Router,
 };
 use chrono::{NaiveDateTime, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Mutex};
 
 #[derive(Serialize, Clone)]
 struct ScorePoint {
@@ -13,190 +14,523 @@ struct ScorePoint {
     score: i32,
 }
 
-// TODO: figure out whether we want pagnation
+// only the top teams are graphed
+const HISTORY_TEAMS: i32 = 10;
+
 #[derive(Serialize)]
 struct LeaderboardEntry {
     #[serde(rename = "id")]
     public_id: String,
     name: String,
     score: i32,
+    rank: i32,
+    solves: i32,
+    last_solve: Option<NaiveDateTime>,
     #[serde(rename(serialize = "extra"))]
     extra_data: serde_json::Value,
     score_history: Vec<ScorePoint>,
 }
 
+#[derive(Serialize)]
+struct LeaderboardPage {
+    // teams in the division matching the search, not just the ones on this page
+    total: i64,
+    teams: Vec<LeaderboardEntry>,
+    // pass as `cursor` to get the next page, missing on the last one
+    next_cursor: Option<i64>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum LeaderboardSort {
+    #[default]
+    Score,
+    Solves,
+    Badges,
+    // most recent first
+    LastSolve,
+}
+
+impl LeaderboardSort {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Score => "score",
+            Self::Solves => "solves",
+            Self::Badges => "badges",
+            Self::LastSolve => "last_solve",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LeaderboardQuery {
+    #[serde(default)]
+    offset: i64,
+    // takes precedence over offset
+    cursor: Option<i64>,
+    limit: Option<i64>,
+    // part of the team name, case insensitive
+    search: Option<String>,
+    #[serde(default)]
+    sort: LeaderboardSort,
+    // the page around the authenticated team instead, `limit` teams big
+    #[serde(default)]
+    around_me: bool,
+    // comma separated, combined with the one in the path
+    divisions: Option<String>,
+    // only count solves of this category's challenges, there's no score history then
+    category: Option<String>,
+    // score history starts here instead of at the start of the event
+    since: Option<NaiveDateTime>,
+}
+
+// teams shown around your own with `around_me` if there's no limit
+const NEIGHBORHOOD_SIZE: i64 = 11;
+
 struct DbLeaderboardEntry {
+    id: i32,
     public_id: String,
     name: String,
     score: i32,
     extra_data: serde_json::Value,
     rank: i32,
+    solves: i32,
+    last_solve: Option<NaiveDateTime>,
+    // position in the requested order
+    pos: i64,
 }
 
-#[derive(sqlx::FromRow, Debug)]
-struct TeamSolvePoint {
-    created_at: NaiveDateTime,
-    points: i32,
+struct ChallPoints {
+    id: i32,
+    points_min: i32,
+    points_max: i32,
+    points_decay: Option<f64>,
 }
 
-async fn calculate_score_history(
-    db: &DB,
-    team_public_id: &str,
-    event_start_time: NaiveDateTime,
-    all_event_timestamps: &[NaiveDateTime],
-) -> Result<Vec<ScorePoint>> {
-    let team_id = sqlx::query_scalar!("SELECT id FROM teams WHERE public_id = $1", team_public_id)
-        .fetch_one(db)
-        .await?;
-
-    let team_solve_points: Vec<TeamSolvePoint> = sqlx::query_as!(
-        TeamSolvePoint,
+/// Score over time of each team, starting at `since` with the score the team had by then. Solves
+/// are worth what their challenge was worth at each point in time, so later solves of a
+/// challenge lower the score of the teams that solved it before.
+async fn score_history(
+    state: &State,
+    team_ids: &[i32],
+    since: NaiveDateTime,
+) -> Result<HashMap<i32, Vec<ScorePoint>>> {
+    // every solve of the challenges these teams solved, the other challenges don't matter
+    let solves = sqlx::query!(
         r#"
-        SELECT s.created_at, ch.c_points as "points!"
-        FROM submissions s
-        JOIN challenges ch ON s.challenge_id = ch.id
-        WHERE s.team_id = $1 AND s.is_correct = TRUE
+        SELECT team_id, challenge_id, created_at FROM submissions
+        WHERE is_correct = true AND challenge_id IN (
+            SELECT challenge_id FROM submissions WHERE is_correct = true AND team_id = ANY($1)
+        )
+        ORDER BY created_at ASC, id ASC
         "#,
-        team_id
+        team_ids
     )
-    .fetch_all(db)
+    .fetch_all(&state.db)
     .await?;
 
-    let mut history = Vec::new();
+    let chall_ids: Vec<i32> = solves.iter().map(|s| s.challenge_id).collect();
+    let challs: HashMap<i32, ChallPoints> = sqlx::query_as!(
+        ChallPoints,
+        "SELECT id, points_min, points_max, points_decay FROM challenges WHERE id = ANY($1)",
+        &chall_ids
+    )
+    .fetch_all(&state.db)
+    .await?
+    .into_iter()
+    .map(|c| (c.id, c))
+    .collect();
+
+    let scoring = &state.event.scoring;
+    let mut histories: HashMap<i32, Vec<ScorePoint>> = team_ids
+        .iter()
+        .map(|&id| {
+            (
+                id,
+                vec![ScorePoint {
+                    date: since,
+                    score: 0,
+                }],
+            )
+        })
+        .collect();
+    // per challenge, how many teams solved it so far and the places of the graphed ones among
+    // them, so each solve only re-prices the graphed teams
+    let mut solvers: HashMap<i32, (i32, Vec<(i32, i32)>)> = HashMap::new();
+    for solve in solves {
+        let Some(chall) = challs.get(&solve.challenge_id) else {
+            continue;
+        };
+        let (count, graphed) = solvers.entry(solve.challenge_id).or_default();
+        let points_at = |solves: i32| {
+            scoring.points(
+                chall.points_min,
+                chall.points_max,
+                chall.points_decay,
+                solves,
+            )
+        };
+        let (old, new) = (points_at(*count), points_at(*count + 1));
+        *count += 1;
+        if histories.contains_key(&solve.team_id) {
+            graphed.push((solve.team_id, *count));
+        }
 
-    if all_event_timestamps.is_empty() {
-        history.push(ScorePoint {
-            date: event_start_time,
-            score: 0,
-        });
-        return Ok(history);
+        for &(team_id, place) in graphed.iter() {
+            // only graphed teams are in here
+            let history = histories.get_mut(&team_id).unwrap();
+            let before = if team_id == solve.team_id {
+                0
+            } else {
+                old + scoring.bonus(place, old)
+            };
+            // never empty, every history starts at `since`
+            let last = history.last_mut().unwrap();
+            let score = last.score - before + new + scoring.bonus(place, new);
+            if score == last.score {
+                continue;
+            }
+            if solve.created_at <= last.date {
+                last.score = score;
+            } else {
+                history.push(ScorePoint {
+                    date: solve.created_at,
+                    score,
+                });
+            }
+        }
     }
 
-    for &ts in all_event_timestamps {
-        let score_at_ts: i32 = team_solve_points
-            .iter()
-            .filter(|solve| solve.created_at <= ts)
-            .map(|solve| solve.points)
-            .sum();
-
-        if history.is_empty() || history.last().map_or(true, |p| p.score != score_at_ts) {
-            history.push(ScorePoint {
-                date: ts,
-                score: score_at_ts,
-            });
+    Ok(histories)
+}
+
+// past this many, the cache starts over rather than growing with every `since` anyone asks for
+const HISTORY_CACHE_SIZE: usize = 10_000;
+
+/// Score histories by team and start. Every solve and every change in points changes the total
+/// number of solves or the sum of every team's score, so histories are kept until either does.
+#[derive(Default)]
+pub(crate) struct HistoryCache {
+    inner: Mutex<HistoryCacheInner>,
+}
+
+#[derive(Default)]
+struct HistoryCacheInner {
+    // total solves and score in team_scores
+    version: (i64, i64),
+    histories: HashMap<(i32, NaiveDateTime), Vec<ScorePoint>>,
+}
+
+/// [score_history], but only computed for the teams whose history changed since it was last asked
+/// for.
+async fn cached_score_history(
+    state: &State,
+    team_ids: &[i32],
+    since: NaiveDateTime,
+) -> Result<HashMap<i32, Vec<ScorePoint>>> {
+    let version = sqlx::query!(
+        r#"SELECT COALESCE(SUM(solves), 0)::bigint AS "solves!", COALESCE(SUM(score), 0)::bigint AS "score!"
+        FROM team_scores"#
+    )
+    .fetch_one(&state.db)
+    .await?;
+    let version = (version.solves, version.score);
+
+    let mut histories = HashMap::new();
+    let missing: Vec<i32> = {
+        let mut cache = state.history_cache.inner.lock().unwrap();
+        if cache.version != version {
+            cache.version = version;
+            cache.histories.clear();
         }
+        team_ids
+            .iter()
+            .copied()
+            .filter(|&id| match cache.histories.get(&(id, since)) {
+                Some(history) => {
+                    histories.insert(id, history.clone());
+                    false
+                }
+                None => true,
+            })
+            .collect()
+    };
+    if missing.is_empty() {
+        return Ok(histories);
     }
 
-    if history.is_empty() {
-        history.push(ScorePoint {
-            date: event_start_time,
-            score: 0,
-        });
+    let computed = score_history(state, &missing, since).await?;
+    let mut cache = state.history_cache.inner.lock().unwrap();
+    // otherwise these might already be outdated
+    if cache.version == version {
+        if cache.histories.len() + computed.len() > HISTORY_CACHE_SIZE {
+            cache.histories.clear();
+        }
+        for (id, history) in &computed {
+            cache.histories.insert((*id, since), history.clone());
+        }
     }
+    histories.extend(computed);
 
-    Ok(history)
+    Ok(histories)
 }
 
-async fn leaderboard(
-    db: &DB,
-    event_start_time: NaiveDateTime,
+fn history_start(state: &State, since: Option<NaiveDateTime>) -> NaiveDateTime {
+    let start = state.event.start_time;
+    since.map_or(start, |since| since.max(start))
+}
+
+fn split_list(list: Option<&str>) -> Vec<String> {
+    list.unwrap_or_default()
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// The divisions to show the leaderboard of, `None` for every team.
+pub(crate) fn divisions(
+    state: &State,
     division: Option<String>,
-) -> Result<Vec<LeaderboardEntry>> {
-    let db_entries = sqlx::query_as!(
-        DbLeaderboardEntry,
-        r#"
-        SELECT t.public_id, t.name, lb.score AS "score!", t.extra_data, lb.rank AS "rank!"
-            FROM teams t 
-            JOIN compute_leaderboard($1) lb ON lb.team_id = t.id
-            ORDER BY lb.rank ASC
-        "#,
-        division
+    divisions: Option<&str>,
+) -> Result<Option<Vec<String>>> {
+    let divisions: Vec<String> = division.into_iter().chain(split_list(divisions)).collect();
+    if divisions
+        .iter()
+        .any(|d| !state.event.divisions.contains_key(d))
+    {
+        return Err(Error::NotFoundDivision);
+    }
+    Ok(Some(divisions).filter(|d| !d.is_empty()))
+}
+
+async fn leaderboard(
+    state: &State,
+    divisions: Option<Vec<String>>,
+    me: Option<&str>,
+    query: LeaderboardQuery,
+) -> Result<LeaderboardPage> {
+    let category = query.category.filter(|c| !c.is_empty());
+    if let Some(category) = &category {
+        let exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM categories WHERE name = $1) AS "exists!""#,
+            category
+        )
+        .fetch_one(&state.db)
+        .await?;
+        if !exists {
+            return Err(Error::NotFoundCategory);
+        }
+    }
+
+    // the neighborhood is about ranks, so it ignores the search
+    let search = query.search.filter(|s| !s.is_empty() && me.is_none());
+    let limit = match (query.limit, me) {
+        (None, Some(_)) => Some(NEIGHBORHOOD_SIZE),
+        (limit, _) => limit.map(|l| l.max(0)),
+    };
+    let start = query.cursor.unwrap_or(query.offset).max(0);
+
+    let total = sqlx::query_scalar!(
+        r#"SELECT count(*) AS "count!" FROM teams t JOIN compute_leaderboard($1) lb ON lb.team_id = t.id
+        WHERE $2::text IS NULL OR strpos(lower(t.name), lower($2)) > 0"#,
+        divisions.as_deref(),
+        search,
     )
-    .fetch_all(db)
+    .fetch_one(&state.db)
     .await?;
 
-    let all_event_timestamps_opt: Vec<Option<NaiveDateTime>> = sqlx::query_scalar!(
+    // outside of category views, the totals come from team_scores, which is kept up to date as
+    // solves come in. with `me`, the page starts so that the team ends up in the middle of it.
+    let db_entries = sqlx::query_as!(
+        DbLeaderboardEntry,
         r#"
-        SELECT times.created_at FROM (
-            SELECT DISTINCT s.created_at FROM submissions s WHERE s.is_correct = TRUE
-            UNION
-            SELECT $1 AS created_at
-        ) AS times
-        ORDER BY times.created_at ASC
+        WITH entries AS (
+            SELECT t.id, t.public_id, t.name, t.extra_data, lb.score, lb.rank, lb.solves, lb.last_solve,
+                jsonb_array_length(COALESCE(t.extra_data->'badges', '[]'::jsonb)) AS badges
+            FROM teams t
+                JOIN compute_leaderboard($1, $7) lb ON lb.team_id = t.id
+            WHERE $2::text IS NULL OR strpos(lower(t.name), lower($2)) > 0
+        ), sorted AS (
+            SELECT *, ROW_NUMBER() OVER (
+                ORDER BY CASE $3 WHEN 'solves' THEN solves WHEN 'badges' THEN badges END DESC NULLS LAST,
+                    CASE WHEN $3 = 'last_solve' THEN last_solve END DESC NULLS LAST,
+                    rank ASC
+            ) AS pos
+            FROM entries
+        )
+        SELECT id AS "id!", public_id AS "public_id!", name AS "name!", extra_data AS "extra_data!",
+            score AS "score!", rank AS "rank!", solves AS "solves!", last_solve, pos AS "pos!"
+        FROM sorted
+        WHERE pos > GREATEST(COALESCE((SELECT pos FROM sorted WHERE public_id = $4) - $6::bigint / 2 - 1, $5), 0)
+        ORDER BY pos ASC
+        LIMIT $6
         "#,
-        event_start_time
+        divisions.as_deref(),
+        search,
+        query.sort.as_str(),
+        me,
+        start,
+        limit,
+        category,
     )
-    .fetch_all(db)
+    .fetch_all(&state.db)
     .await?;
 
-    let all_event_timestamps: Vec<NaiveDateTime> = all_event_timestamps_opt
+    if let Some(me) = me {
+        if !db_entries.iter().any(|e| e.public_id == me) {
+            return Err(Error::NotFoundTeam);
+        }
+    }
+
+    let next_cursor = db_entries
+        .last()
+        .filter(|e| limit.is_some() && e.pos < total)
+        .map(|e| e.pos);
+    let graphed: Vec<i32> = db_entries
+        .iter()
+        .filter(|e| category.is_none() && e.rank <= HISTORY_TEAMS)
+        .map(|e| e.id)
+        .collect();
+    let mut histories =
+        cached_score_history(state, &graphed, history_start(state, query.since)).await?;
+
+    let teams = db_entries
         .into_iter()
-        .filter_map(|x| x)
+        .map(|e| LeaderboardEntry {
+            score_history: histories.remove(&e.id).unwrap_or_default(),
+            public_id: e.public_id,
+            name: e.name,
+            score: e.score,
+            rank: e.rank,
+            solves: e.solves,
+            last_solve: e.last_solve,
+            extra_data: e.extra_data,
+        })
         .collect();
 
-    let mut leaderboard_entries = Vec::new();
-    let default_history_point = ScorePoint {
-        date: event_start_time,
-        score: 0,
-    };
+    Ok(LeaderboardPage {
+        total,
+        teams,
+        next_cursor,
+    })
+}
 
-    for db_entry in db_entries {
-        let score_history = if leaderboard_entries.len() < 10 {
-            match calculate_score_history(
-                db,
-                &db_entry.public_id,
-                event_start_time,
-                &all_event_timestamps,
-            )
-            .await
-            {
-                Ok(history) => history,
-                Err(_e) => {
-                    vec![default_history_point.clone()]
-                }
-            }
-        } else {
-            Vec::new()
-        };
+pub(crate) struct Standing {
+    pub public_id: String,
+    pub name: String,
+    pub division: Option<String>,
+    pub score: i32,
+    pub rank: i32,
+    pub solves: i32,
+    pub last_solve: Option<NaiveDateTime>,
+}
 
-        leaderboard_entries.push(LeaderboardEntry {
-            public_id: db_entry.public_id,
-            name: db_entry.name,
-            score: db_entry.score,
-            extra_data: db_entry.extra_data,
-            score_history,
-        });
-    }
+/// Every team eligible for any of the divisions, or every team, in rank order.
+pub(crate) async fn standings(db: &DB, divisions: Option<&[String]>) -> Result<Vec<Standing>> {
+    Ok(sqlx::query_as!(
+        Standing,
+        r#"
+        SELECT t.public_id, t.name, t.division, lb.score AS "score!", lb.rank AS "rank!",
+            lb.solves AS "solves!", lb.last_solve
+            FROM teams t
+            JOIN compute_leaderboard($1) lb ON lb.team_id = t.id
+            ORDER BY lb.rank ASC
+        "#,
+        divisions
+    )
+    .fetch_all(db)
+    .await?)
+}
 
-    Ok(leaderboard_entries)
+fn check_started(state: &State) -> Result<()> {
+    if Utc::now().naive_utc() < state.event.start_time {
+        return Err(Error::EventNotStarted(state.event.start_time));
+    }
+    Ok(())
 }
 
 async fn get_lb(
     StateE(state): StateE<State>,
+    auth: Option<Auth>,
     division: Option<Path<String>>,
-) -> Result<Json<Vec<LeaderboardEntry>>> {
-    if Utc::now().naive_utc() < state.event.start_time {
-        return Err(Error::EventNotStarted(state.event.start_time.clone()));
-    }
+    Query(query): Query<LeaderboardQuery>,
+) -> Result<Json<LeaderboardPage>> {
+    check_started(&state)?;
+    let divisions = divisions(&state, division.map(|x| x.0), query.divisions.as_deref())?;
 
-    let division = division.map(|x| x.0);
-    if division != None
-        && state
-            .event
-            .divisions
-            .get(division.as_ref().unwrap())
-            .is_none()
-    {
-        return Err(Error::NotFoundDivision);
-    }
+    let me = match (query.around_me, &auth) {
+        (false, _) => None,
+        (true, Some(Auth(claims))) => Some(claims.team_id.as_str()),
+        (true, None) => return Err(Error::InvalidToken),
+    };
+    leaderboard(&state, divisions, me, query).await.map(Json)
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    // comma separated public ids
+    teams: Option<String>,
+    // also include the top teams of the leaderboard
+    top: Option<i32>,
+    // comma separated, the top teams are of these
+    divisions: Option<String>,
+    since: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+struct TeamHistory {
+    id: String,
+    name: String,
+    score_history: Vec<ScorePoint>,
+}
+
+const MAX_HISTORY_TEAMS: i64 = 50;
+
+async fn get_history(
+    StateE(state): StateE<State>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<TeamHistory>>> {
+    check_started(&state)?;
+    let divisions = divisions(&state, None, query.divisions.as_deref())?;
+
+    let public_ids = split_list(query.teams.as_deref());
+    // top teams first, then the requested ones outside of the division
+    let teams = sqlx::query!(
+        r#"
+        SELECT t.id, t.public_id, t.name FROM teams t
+            LEFT JOIN compute_leaderboard($2) lb ON lb.team_id = t.id
+            WHERE t.public_id = ANY($1) OR lb.rank <= $3
+            ORDER BY lb.rank ASC NULLS LAST, t.id ASC
+            LIMIT $4
+        "#,
+        &public_ids,
+        divisions.as_deref(),
+        query.top.unwrap_or(0),
+        MAX_HISTORY_TEAMS,
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let ids: Vec<i32> = teams.iter().map(|t| t.id).collect();
+    let mut histories =
+        cached_score_history(&state, &ids, history_start(&state, query.since)).await?;
 
-    return leaderboard(&state.db, state.event.start_time, division)
-        .await
-        .map(Json);
+    Ok(Json(
+        teams
+            .into_iter()
+            .map(|t| TeamHistory {
+                score_history: histories.remove(&t.id).unwrap_or_default(),
+                id: t.public_id,
+                name: t.name,
+            })
+            .collect(),
+    ))
 }
 
 pub fn router() -> Router<crate::State> {
     Router::new()
         .route("/", get(get_lb))
+        .route("/history", get(get_history))
         .route("/{division}", get(get_lb))
 }
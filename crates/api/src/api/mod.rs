@@ -2,15 +2,18 @@ use axum::Router;
 
 mod auth;
 mod challenges;
-mod leaderboard;
+pub(crate) mod leaderboard;
 mod profile;
+pub(crate) mod users;
 
 pub fn router() -> Router<crate::State> {
     Router::new()
         .nest("/admin", crate::admin::router())
+        .nest("/attachments", crate::attachments::router())
         .nest("/auth", auth::router())
         .nest("/challs", challenges::router())
         .nest("/event", crate::event::router())
         .nest("/profile", profile::router())
         .nest("/leaderboard", leaderboard::router())
+        .nest("/users", users::router())
 }
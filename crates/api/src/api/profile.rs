@@ -12,10 +12,12 @@ use super::auth::{Team, TeamInfo, VerificationRequest};
 
 async fn update(
     StateE(state): StateE<State>,
-    Auth(Claims { team_id, .. }): Auth,
+    Auth(claims): Auth,
     Json(payload): Json<TeamInfo>,
 ) -> Result<Json<serde_json::Value>> {
     payload.validate()?;
+    super::users::check_captain(&state.db, &claims).await?;
+    let Claims { team_id, .. } = claims;
     let trimmed_name = payload.name.trim();
 
     let current_team = sqlx::query!(
@@ -104,6 +106,8 @@ pub(crate) struct Solve {
     name: String,
     category: String,
     points: i32,
+    // blood bonus points
+    bonus: i32,
     pub(crate) solved_at: NaiveDateTime,
 }
 
@@ -111,10 +115,10 @@ pub(crate) async fn get_solves(db: &DB, pub_id: &str) -> Result<Vec<Solve>> {
     let solves = sqlx::query_as!(
         Solve,
         r#"WITH 
-            team AS (SELECT id FROM teams WHERE public_id = $1),
-            solved_challs AS (SELECT challenge_id AS id, created_at FROM submissions, team WHERE is_correct = true AND team_id = team.id)
-        SELECT public_id, c.name, cg.name AS category, c_points AS points, created_at AS solved_at FROM challenges c JOIN solved_challs sc ON sc.id = c.id JOIN categories cg ON cg.id = c.category_id
-        ORDER BY solved_at DESC"#,
+            team AS (SELECT id FROM teams WHERE public_id = $1)
+        SELECT public_id, c.name, cg.name AS category, s.points AS "points!", s.bonus AS "bonus!", s.created_at AS "solved_at!"
+        FROM scored_solves() s JOIN team ON team.id = s.team_id JOIN challenges c ON c.id = s.challenge_id JOIN categories cg ON cg.id = c.category_id
+        ORDER BY s.created_at DESC"#,
         pub_id
     ).fetch_all(db).await?;
 
@@ -162,7 +166,8 @@ async fn profile(
         TeamDetails,
         r#"
         SELECT name, email, division, rank, score FROM teams t
-            JOIN compute_leaderboard(t.division) lb ON lb.team_id = t.id 
+            JOIN compute_leaderboard(CASE WHEN t.division IS NULL THEN NULL ELSE ARRAY[t.division] END) lb
+                ON lb.team_id = t.id
             WHERE t.id = (SELECT id FROM teams WHERE public_id = $1)"#,
         pub_id
     )
@@ -0,0 +1,487 @@
+use axum::{
+    extract::State as StateE,
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use chrono::Duration;
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use sqlx::PgTransaction;
+use validator::Validate;
+
+use super::auth::{ResendTokenRequest, TeamId, VerificationRequest};
+use crate::{
+    email::PendingUserVerification,
+    extractors::{Auth, UserAuth},
+    jwt::{generate_user_jwt, Claims},
+    Error, Result, State, DB,
+};
+
+#[derive(Deserialize, Validate)]
+struct UserInfo {
+    #[validate(length(min = 1, max = 72))]
+    name: String,
+    #[validate(email)]
+    email: String,
+}
+
+#[derive(Deserialize, Validate)]
+struct NewTeam {
+    #[validate(length(min = 1, max = 72))]
+    name: String,
+    division: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JoinRequest {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct MemberRequest {
+    user_id: String,
+}
+
+/// Public id of the user's team, if they're in one.
+pub(crate) async fn team_of(db: &DB, user_id: &str) -> Result<Option<String>> {
+    Ok(sqlx::query_scalar!(
+        "SELECT t.public_id FROM users u JOIN teams t ON t.id = u.team_id WHERE u.public_id = $1",
+        user_id
+    )
+    .fetch_optional(db)
+    .await?)
+}
+
+/// Team tokens and captains can manage the team, other members can't.
+pub(crate) async fn check_captain(db: &DB, claims: &Claims) -> Result<()> {
+    let Some(user_id) = &claims.user_id else {
+        return Ok(());
+    };
+
+    let captain = sqlx::query_scalar!("SELECT captain FROM users WHERE public_id = $1", user_id)
+        .fetch_optional(db)
+        .await?;
+    if captain == Some(true) {
+        Ok(())
+    } else {
+        Err(Error::NotCaptain)
+    }
+}
+
+async fn team_id(db: &DB, public_id: &str) -> Result<i32> {
+    sqlx::query_scalar!("SELECT id FROM teams WHERE public_id = $1", public_id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(Error::NotFoundTeam)
+}
+
+async fn register(
+    StateE(state): StateE<State>,
+    Json(payload): Json<UserInfo>,
+) -> Result<(StatusCode, Json<serde_json::Value>)> {
+    payload.validate()?;
+
+    let taken = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM users WHERE email = $1) AS "taken!""#,
+        payload.email
+    )
+    .fetch_one(&state.db)
+    .await?;
+    if taken {
+        return Err(Error::EmailTaken);
+    }
+
+    state
+        .email
+        .send_user_verification_email(
+            &state.event,
+            PendingUserVerification {
+                name: payload.name.trim().to_string(),
+                email: payload.email.clone(),
+            },
+        )
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({
+            "email": payload.email
+        })),
+    ))
+}
+
+async fn verify(
+    StateE(state): StateE<State>,
+    jar: CookieJar,
+    Json(VerificationRequest { token }): Json<VerificationRequest>,
+) -> Result<(CookieJar, Json<TeamId>)> {
+    let details = state
+        .email
+        .consume_pending_user_verification(&token)
+        .await?;
+
+    // two registrations for the same email can both be pending
+    let public_id = sqlx::query_scalar!(
+        "INSERT INTO users (public_id, name, email) VALUES ($1, $2, $3)
+            ON CONFLICT (email) DO NOTHING
+            RETURNING public_id",
+        nanoid!(),
+        details.name,
+        details.email
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(Error::EmailTaken)?;
+
+    let jwt = generate_user_jwt(&state.config.jwt_keys, &public_id, Duration::days(30))?;
+
+    let mut cookie = Cookie::new("token", jwt);
+    cookie.set_path("/");
+    cookie.set_max_age(time::Duration::days(30));
+    Ok((jar.add(cookie), Json(TeamId { id: None })))
+}
+
+async fn resend_token(
+    StateE(state): StateE<State>,
+    Json(payload): Json<ResendTokenRequest>,
+) -> Result<StatusCode> {
+    payload.validate()?;
+
+    let user = sqlx::query!(
+        "SELECT public_id, name, email FROM users WHERE email = $1",
+        payload.email
+    )
+    .fetch_optional(&state.db)
+    .await?;
+
+    if let Some(user) = user {
+        let jwt = generate_user_jwt(&state.config.jwt_keys, &user.public_id, Duration::days(30))?;
+        state
+            .email
+            .send_user_token_email(&state.event, &user.email, &user.name, &jwt)
+            .await?;
+    }
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Serialize)]
+struct Me {
+    id: String,
+    name: String,
+    email: String,
+    team_id: Option<String>,
+    team_name: Option<String>,
+    captain: bool,
+}
+
+async fn me(StateE(state): StateE<State>, UserAuth(user_id): UserAuth) -> Result<Json<Me>> {
+    let me = sqlx::query_as!(
+        Me,
+        r#"SELECT u.public_id AS id, u.name, u.email, t.public_id AS "team_id?", t.name AS "team_name?",
+            u.captain AND t.id IS NOT NULL AS "captain!"
+            FROM users u LEFT JOIN teams t ON t.id = u.team_id
+            WHERE u.public_id = $1"#,
+        user_id
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(Error::NotFoundUser)?;
+
+    Ok(Json(me))
+}
+
+async fn create_team(
+    StateE(state): StateE<State>,
+    UserAuth(user_id): UserAuth,
+    Json(payload): Json<NewTeam>,
+) -> Result<Json<TeamId>> {
+    payload.validate()?;
+    let name = payload.name.trim();
+
+    if let Some(division) = &payload.division {
+        if !state.event.divisions.contains_key(division) {
+            return Err(Error::NotFoundDivision);
+        }
+    }
+
+    let mut tx = state.db.begin().await?;
+    let user = sqlx::query!(
+        "SELECT id, email, team_id FROM users WHERE public_id = $1 FOR UPDATE",
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::NotFoundUser)?;
+    if user.team_id.is_some() {
+        return Err(Error::AlreadyInTeam);
+    }
+
+    let existing = sqlx::query!(
+        r#"SELECT name = $1 AS "name_taken!" FROM teams WHERE name = $1 OR email = $2"#,
+        name,
+        user.email
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    match existing {
+        Some(e) if e.name_taken => return Err(Error::TeamNameTaken),
+        // the team email is the captain's, which already belongs to a team registered without accounts
+        Some(_) => return Err(Error::EmailTaken),
+        None => {}
+    }
+
+    let team = sqlx::query!(
+        "INSERT INTO teams (public_id, name, email, division) VALUES ($1, $2, $3, $4)
+            RETURNING id, public_id",
+        nanoid!(),
+        name,
+        user.email,
+        payload.division
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE users SET team_id = $1, captain = true, joined_at = NOW() WHERE id = $2",
+        team.id,
+        user.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(TeamId {
+        id: Some(team.public_id),
+    }))
+}
+
+async fn join(
+    StateE(state): StateE<State>,
+    UserAuth(user_id): UserAuth,
+    Json(JoinRequest { code }): Json<JoinRequest>,
+) -> Result<Json<TeamId>> {
+    let mut tx = state.db.begin().await?;
+
+    // locking the team serializes joins, so the size limit holds
+    let team = sqlx::query!(
+        "SELECT t.id, t.public_id FROM team_invites i JOIN teams t ON t.id = i.team_id
+            WHERE i.code = $1
+            FOR UPDATE OF t",
+        code
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::NotFoundTeam)?;
+
+    if let Some(max) = state.event.max_team_size {
+        let members = sqlx::query_scalar!(
+            r#"SELECT count(*) AS "count!" FROM users WHERE team_id = $1"#,
+            team.id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if members >= max {
+            return Err(Error::TeamFull);
+        }
+    }
+
+    // teams registered with a token have no captain until someone joins
+    let joined = sqlx::query!(
+        "UPDATE users SET team_id = $1, joined_at = NOW(),
+            captain = NOT EXISTS(SELECT 1 FROM users WHERE team_id = $1 AND captain)
+            WHERE public_id = $2 AND team_id IS NULL",
+        team.id,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if joined == 0 {
+        return Err(Error::AlreadyInTeam);
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(TeamId {
+        id: Some(team.public_id),
+    }))
+}
+
+/// Takes the user out of the team, handing the captaincy to the longest standing member if needed.
+async fn remove_member(tx: &mut PgTransaction<'_>, user_id: &str, team_id: i32) -> Result<()> {
+    let removed = sqlx::query!(
+        "UPDATE users SET team_id = NULL, captain = false, joined_at = NULL
+            WHERE public_id = $1 AND team_id = $2",
+        user_id,
+        team_id
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+    if removed == 0 {
+        return Err(Error::NotFoundUser);
+    }
+
+    sqlx::query!(
+        "UPDATE users SET captain = true
+            WHERE id = (SELECT id FROM users WHERE team_id = $1 ORDER BY joined_at, id LIMIT 1)
+            AND NOT EXISTS(SELECT 1 FROM users WHERE team_id = $1 AND captain)",
+        team_id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn leave(StateE(state): StateE<State>, UserAuth(user_id): UserAuth) -> Result<StatusCode> {
+    let mut tx = state.db.begin().await?;
+    let team_id = sqlx::query_scalar!(
+        "SELECT team_id FROM users WHERE public_id = $1 FOR UPDATE",
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .flatten()
+    .ok_or(Error::NotInTeam)?;
+
+    remove_member(&mut tx, &user_id, team_id).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn kick(
+    StateE(state): StateE<State>,
+    Auth(claims): Auth,
+    Json(MemberRequest { user_id }): Json<MemberRequest>,
+) -> Result<StatusCode> {
+    check_captain(&state.db, &claims).await?;
+    let team_id = team_id(&state.db, &claims.team_id).await?;
+
+    let mut tx = state.db.begin().await?;
+    remove_member(&mut tx, &user_id, team_id).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn transfer_captain(
+    StateE(state): StateE<State>,
+    Auth(claims): Auth,
+    Json(MemberRequest { user_id }): Json<MemberRequest>,
+) -> Result<StatusCode> {
+    check_captain(&state.db, &claims).await?;
+    let team_id = team_id(&state.db, &claims.team_id).await?;
+
+    let mut tx = state.db.begin().await?;
+    // there can only be one captain at a time, so the old one steps down first
+    sqlx::query!(
+        "UPDATE users SET captain = false WHERE team_id = $1",
+        team_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    let promoted = sqlx::query!(
+        "UPDATE users SET captain = true WHERE public_id = $1 AND team_id = $2",
+        user_id,
+        team_id
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if promoted == 0 {
+        return Err(Error::NotFoundUser);
+    }
+    tx.commit().await?;
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Serialize)]
+struct Member {
+    id: String,
+    name: String,
+    captain: bool,
+    // solves submitted by this member
+    solves: i64,
+}
+
+#[derive(Serialize)]
+struct TeamMembers {
+    invite_code: String,
+    members: Vec<Member>,
+}
+
+async fn team(StateE(state): StateE<State>, Auth(claims): Auth) -> Result<Json<TeamMembers>> {
+    let team_id = team_id(&state.db, &claims.team_id).await?;
+
+    let members = sqlx::query_as!(
+        Member,
+        r#"SELECT u.public_id AS id, u.name, u.captain,
+            (SELECT count(*) FROM submissions s WHERE s.user_id = u.id AND s.is_correct) AS "solves!"
+            FROM users u
+            WHERE u.team_id = $1
+            ORDER BY u.joined_at, u.id"#,
+        team_id
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    // created on first use, most teams that register with a token never need one
+    sqlx::query!(
+        "INSERT INTO team_invites (team_id, code) VALUES ($1, $2) ON CONFLICT (team_id) DO NOTHING",
+        team_id,
+        nanoid!()
+    )
+    .execute(&state.db)
+    .await?;
+    let invite_code =
+        sqlx::query_scalar!("SELECT code FROM team_invites WHERE team_id = $1", team_id)
+            .fetch_one(&state.db)
+            .await?;
+
+    Ok(Json(TeamMembers {
+        invite_code,
+        members,
+    }))
+}
+
+async fn rotate_invite(
+    StateE(state): StateE<State>,
+    Auth(claims): Auth,
+) -> Result<Json<serde_json::Value>> {
+    check_captain(&state.db, &claims).await?;
+    let team_id = team_id(&state.db, &claims.team_id).await?;
+
+    let invite_code = sqlx::query_scalar!(
+        "INSERT INTO team_invites (team_id, code) VALUES ($1, $2)
+            ON CONFLICT (team_id) DO UPDATE SET code = EXCLUDED.code
+            RETURNING code",
+        team_id,
+        nanoid!()
+    )
+    .fetch_one(&state.db)
+    .await?;
+
+    Ok(Json(serde_json::json!({ "invite_code": invite_code })))
+}
+
+pub fn router() -> Router<State> {
+    Router::new()
+        .route("/register", post(register))
+        .route("/verify", post(verify))
+        .route("/resend_token", post(resend_token))
+        .route("/me", get(me))
+        .route("/team", get(team))
+        .route("/team/create", post(create_team))
+        .route("/team/join", post(join))
+        .route("/team/leave", post(leave))
+        .route("/team/kick", post(kick))
+        .route("/team/captain", post(transfer_captain))
+        .route("/team/invite/rotate", post(rotate_invite))
+}
@@ -0,0 +1,59 @@
+use std::path::{Component, Path, PathBuf};
+
+use axum::{
+    extract::{Path as PathE, State as StateE},
+    http::header,
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
+};
+
+use crate::{Error, Result, State};
+
+// attachment keys come from the url, so make sure they can't escape the attachments dir
+pub fn attachment_path(dir: &Path, key: &str) -> Result<PathBuf> {
+    let key = Path::new(key);
+    if key.as_os_str().is_empty() || !key.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(Error::NotFoundAttachment);
+    }
+
+    Ok(dir.join(key))
+}
+
+async fn download(StateE(state): StateE<State>, PathE(key): PathE<String>) -> Result<Response> {
+    let path = attachment_path(&state.config.attachments_dir, &key)?;
+    let data = match tokio::fs::read(&path).await {
+        Ok(d) => d,
+        // not pushed here, so it might be in a private bucket, hand out a fresh link to it
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let Some(storage) = &state.attachment_storage else {
+                return Err(Error::NotFoundAttachment);
+            };
+            return match storage.signed_url(&key).await.map_err(Error::Storage)? {
+                Some(url) => Ok(Redirect::temporary(&url).into_response()),
+                None => Err(Error::NotFoundAttachment),
+            };
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("attachment");
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_owned()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", name.replace('"', "")),
+            ),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+pub fn router() -> Router<crate::State> {
+    Router::new().route("/{*key}", get(download))
+}
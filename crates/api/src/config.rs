@@ -1,9 +1,11 @@
-use std::{str::FromStr, sync::Arc};
+use std::{path::PathBuf, str::FromStr, sync::Arc};
 
 use envconfig::Envconfig;
 use jsonwebtoken::{DecodingKey, EncodingKey};
 
-use crate::{email, event::Event, DB};
+use deployer_common::storage::Storage;
+
+use crate::{api::leaderboard::HistoryCache, email, event::Event, DB};
 
 pub struct JwtKeys {
     pub encoding: EncodingKey,
@@ -49,6 +51,19 @@ pub struct Config {
 
     #[envconfig(from = "BLOODBOT_DISCORD_WEBHOOK_URL")]
     pub bloodbot_discord_webhook_url: Option<String>,
+
+    // discord compatible, posted to when challenges with a release_at are released
+    #[envconfig(from = "RELEASE_WEBHOOK_URL")]
+    pub release_webhook_url: Option<String>,
+
+    // attachments pushed with the api storage backend are kept here
+    #[envconfig(from = "ATTACHMENTS_DIR", default = "attachments")]
+    pub attachments_dir: PathBuf,
+
+    // gcs or s3, with the same GCS_*/S3_* vars the cli pushes with, private attachments there
+    // are downloaded through here so their links can be re-signed
+    #[envconfig(from = "ATTACHMENTS_STORAGE")]
+    pub attachments_storage: Option<String>,
 }
 
 pub struct StateInner {
@@ -56,6 +71,46 @@ pub struct StateInner {
     pub event: Event,
     pub db: DB,
     pub email: email::EmailService,
+    pub attachment_storage: Option<Storage>,
+    pub history_cache: HistoryCache,
+}
+
+#[cfg(test)]
+impl StateInner {
+    /// State for calling handlers in tests, without a mailer, remote attachments or webhooks.
+    pub fn for_tests(db: DB) -> State {
+        let config = Config {
+            database_url: String::new(),
+            jwt_keys: "c2VjcmV0".parse().unwrap(),
+            admin_token: String::new(),
+            event_path: String::new(),
+            cors_origin: String::new(),
+            smtp_url: String::new(),
+            from_email: String::new(),
+            deployer_base: String::new(),
+            bloodbot_discord_webhook_url: None,
+            release_webhook_url: None,
+            attachments_dir: Default::default(),
+            attachments_storage: None,
+        };
+        let event = toml::from_str(
+            r#"
+            name = "test"
+            description = ""
+            start_time = "2000-01-01T00:00:00"
+            end_time = "2100-01-01T00:00:00"
+            "#,
+        )
+        .unwrap();
+        State::new(Self {
+            email: email::EmailService::new(&config),
+            attachment_storage: None,
+            history_cache: Default::default(),
+            config,
+            event,
+            db,
+        })
+    }
 }
 
 impl AsRef<Config> for StateInner {
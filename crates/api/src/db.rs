@@ -1,29 +1,42 @@
-use crate::event::point_formula;
+use crate::event::{BloodBonusKind, Event, Scoring};
 use serde::{Deserialize, Serialize};
-use sqlx::{Pool, Postgres};
+use sqlx::{PgTransaction, Pool, Postgres};
 
 pub type DB = Pool<Postgres>;
-// TODO im pretty sure the transaction needs to start earlier
-pub async fn update_chall_cache(db: &DB, chall_id: i32) -> crate::Result<()> {
+/// Recomputes the cached solves and points of a challenge, and the leaderboard totals of the teams
+/// that solved it, as part of `tx`, returning the new points. The challenge row stays locked until
+/// `tx` ends, so concurrent solves are counted one after another.
+pub async fn update_chall_cache_tx(
+    tx: &mut PgTransaction<'_>,
+    scoring: &Scoring,
+    chall_id: i32,
+) -> crate::Result<i32> {
     #[derive(Serialize)]
     struct ChallDetails {
         points_min: i32,
         points_max: i32,
+        points_decay: Option<f64>,
         solves: i32,
     }
 
-    let mut tx = db.begin().await?;
+    sqlx::query!(
+        "SELECT id FROM challenges WHERE id = $1 FOR UPDATE",
+        chall_id
+    )
+    .fetch_one(&mut **tx)
+    .await?;
 
     let chall_details = sqlx::query_as!(
         ChallDetails,
         r#"WITH solves as (SELECT count(*)::int AS solves FROM submissions WHERE is_correct = true AND challenge_id = $1)
-        SELECT points_min, points_max, solves AS "solves!" FROM challenges c, solves WHERE id = $1"#,
+        SELECT points_min, points_max, points_decay, solves AS "solves!" FROM challenges c, solves WHERE id = $1"#,
         chall_id
-    ).fetch_one(&mut *tx).await?;
+    ).fetch_one(&mut **tx).await?;
 
-    let points = point_formula(
+    let points = scoring.points(
         chall_details.points_min,
         chall_details.points_max,
+        chall_details.points_decay,
         chall_details.solves,
     );
 
@@ -33,10 +46,76 @@ pub async fn update_chall_cache(db: &DB, chall_id: i32) -> crate::Result<()> {
         points,
         chall_id,
     )
+    .execute(&mut **tx)
+    .await?;
+
+    // every solver's total depends on the challenge's points
+    sqlx::query("SELECT refresh_team_scores($1)")
+        .bind(chall_id)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(points)
+}
+
+pub async fn update_chall_cache(db: &DB, scoring: &Scoring, chall_id: i32) -> crate::Result<()> {
+    let mut tx = db.begin().await?;
+    update_chall_cache_tx(&mut tx, scoring, chall_id).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Replaces the blood bonuses used by `compute_leaderboard` with the ones from the event config.
+pub async fn sync_blood_bonuses(db: &DB, scoring: &Scoring) -> crate::Result<()> {
+    let places: Vec<i32> = (1..=scoring.blood_bonus.len() as i32).collect();
+    let (points, percent) = match scoring.blood_bonus_kind {
+        BloodBonusKind::Points => (scoring.blood_bonus.clone(), vec![0; places.len()]),
+        BloodBonusKind::Percent => (vec![0; places.len()], scoring.blood_bonus.clone()),
+    };
+
+    let mut tx = db.begin().await?;
+    sqlx::query!("DELETE FROM blood_bonuses")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        "INSERT INTO blood_bonuses (place, points, percent) SELECT * FROM UNNEST($1::int[], $2::int[], $3::int[])",
+        &places,
+        &points,
+        &percent,
+    )
     .execute(&mut *tx)
     .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
 
+/// Replaces the division eligibility used by `compute_leaderboard` with the one from the event config.
+pub async fn sync_division_includes(db: &DB, event: &Event) -> crate::Result<()> {
+    let (divisions, includes): (Vec<String>, Vec<String>) = event
+        .divisions
+        .keys()
+        .flat_map(|d| {
+            event
+                .included_divisions(d)
+                .into_iter()
+                .map(move |i| (d.clone(), i))
+        })
+        .unzip();
+
+    let mut tx = db.begin().await?;
+    sqlx::query!("DELETE FROM division_includes")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        "INSERT INTO division_includes (division, includes) SELECT * FROM UNNEST($1::text[], $2::text[])",
+        &divisions,
+        &includes,
+    )
+    .execute(&mut *tx)
+    .await?;
     tx.commit().await?;
+
     Ok(())
 }
 
@@ -47,3 +126,220 @@ pub enum DeploymentStrategy {
     Static,
     Instanced,
 }
+
+// needs DATABASE_URL to point at a postgres server, every test gets its own database
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::ScoringFormula;
+    use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+
+    // 500 points, 100 less per solve, and 50 for first blood
+    fn scoring() -> Scoring {
+        Scoring {
+            formula: ScoringFormula::Linear,
+            decay: 4.0,
+            blood_bonus: vec![50],
+            ..Default::default()
+        }
+    }
+
+    fn at(minute: i64) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            + TimeDelta::minutes(minute)
+    }
+
+    async fn team(db: &DB, name: &str, division: &str) -> i32 {
+        sqlx::query_scalar(
+            "INSERT INTO teams (public_id, name, email, division) VALUES ($1, $1, $1 || '@example.com', $2) RETURNING id",
+        )
+        .bind(name)
+        .bind(division)
+        .fetch_one(db)
+        .await
+        .unwrap()
+    }
+
+    async fn chall(db: &DB, name: &str, category: &str) -> i32 {
+        sqlx::query("INSERT INTO categories (name) VALUES ($1) ON CONFLICT DO NOTHING")
+            .bind(category)
+            .execute(db)
+            .await
+            .unwrap();
+        sqlx::query_scalar(
+            r#"INSERT INTO challenges (public_id, name, points_min, points_max, flag, attachments, category_id, author)
+            SELECT $1, $1, 100, 500, 'flag', '{}', id, 'author' FROM categories WHERE name = $2 RETURNING id"#,
+        )
+        .bind(name)
+        .bind(category)
+        .fetch_one(db)
+        .await
+        .unwrap()
+    }
+
+    async fn solve(db: &DB, team: i32, chall: i32, minute: i64) {
+        sqlx::query(
+            "INSERT INTO submissions (submission, is_correct, team_id, challenge_id, created_at) VALUES ('flag', true, $1, $2, $3)",
+        )
+        .bind(team)
+        .bind(chall)
+        .bind(at(minute))
+        .execute(db)
+        .await
+        .unwrap();
+        update_chall_cache(db, &scoring(), chall).await.unwrap();
+    }
+
+    async fn team_scores(db: &DB) -> Vec<(i32, i32, i32, Option<NaiveDateTime>)> {
+        sqlx::query_as(
+            "SELECT team_id, score, solves, last_solve FROM team_scores ORDER BY team_id",
+        )
+        .fetch_all(db)
+        .await
+        .unwrap()
+    }
+
+    async fn leaderboard(
+        db: &DB,
+        divisions: Option<&[&str]>,
+        category: Option<&str>,
+    ) -> Vec<(i32, i32, i32)> {
+        sqlx::query_as("SELECT team_id, score, rank FROM compute_leaderboard($1, $2) ORDER BY rank")
+            .bind(divisions)
+            .bind(category)
+            .fetch_all(db)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn scored_solves(db: DB) {
+        sync_blood_bonuses(&db, &scoring()).await.unwrap();
+        let (a, b) = (team(&db, "a", "open").await, team(&db, "b", "open").await);
+        let (web, pwn) = (
+            chall(&db, "web", "web").await,
+            chall(&db, "pwn", "pwn").await,
+        );
+        solve(&db, b, web, 2).await;
+        solve(&db, a, web, 1).await;
+        solve(&db, b, pwn, 3).await;
+
+        let solves: Vec<(i32, i32, i32, i32, i32)> = sqlx::query_as(
+            "SELECT team_id, challenge_id, place, points, bonus FROM scored_solves() ORDER BY challenge_id, place",
+        )
+        .fetch_all(&db)
+        .await
+        .unwrap();
+        // places follow solve times, not insertion order
+        assert_eq!(
+            solves,
+            [
+                (a, web, 1, 300, 50),
+                (b, web, 2, 300, 0),
+                (b, pwn, 1, 400, 50)
+            ]
+        );
+
+        let pwn_solves: Vec<(i32, i32)> =
+            sqlx::query_as("SELECT team_id, place FROM scored_solves($1)")
+                .bind(pwn)
+                .fetch_all(&db)
+                .await
+                .unwrap();
+        assert_eq!(pwn_solves, [(b, 1)]);
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn refresh_team_scores_incrementally(db: DB) {
+        sync_blood_bonuses(&db, &scoring()).await.unwrap();
+        let (a, b, c) = (
+            team(&db, "a", "open").await,
+            team(&db, "b", "open").await,
+            team(&db, "c", "open").await,
+        );
+        let (web, pwn) = (
+            chall(&db, "web", "web").await,
+            chall(&db, "pwn", "pwn").await,
+        );
+        solve(&db, a, web, 1).await;
+        solve(&db, b, pwn, 2).await;
+        solve(&db, b, web, 3).await;
+        solve(&db, c, web, 4).await;
+
+        // web dropped to 200 with each solve, which also lowered a's total
+        let incremental = team_scores(&db).await;
+        assert_eq!(
+            incremental,
+            [
+                (a, 250, 1, Some(at(1))),
+                (b, 650, 2, Some(at(3))),
+                (c, 200, 1, Some(at(4))),
+            ]
+        );
+
+        sqlx::query("SELECT refresh_team_scores()")
+            .execute(&db)
+            .await
+            .unwrap();
+        assert_eq!(team_scores(&db).await, incremental);
+
+        // refreshing a challenge nothing changed about is a no-op
+        sqlx::query("SELECT refresh_team_scores($1)")
+            .bind(pwn)
+            .execute(&db)
+            .await
+            .unwrap();
+        assert_eq!(team_scores(&db).await, incremental);
+    }
+
+    #[sqlx::test(migrations = "../../migrations")]
+    async fn compute_leaderboard(db: DB) {
+        sync_blood_bonuses(&db, &scoring()).await.unwrap();
+        let (a, b, c) = (
+            team(&db, "a", "open").await,
+            team(&db, "b", "student").await,
+            team(&db, "c", "student").await,
+        );
+        let (web, pwn) = (
+            chall(&db, "web", "web").await,
+            chall(&db, "pwn", "pwn").await,
+        );
+        solve(&db, a, web, 1).await;
+        solve(&db, b, web, 2).await;
+        solve(&db, b, pwn, 3).await;
+        sqlx::query(
+            "INSERT INTO division_includes (division, includes) VALUES ('open', 'student')",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            leaderboard(&db, None, None).await,
+            [(b, 750, 1), (a, 350, 2), (c, 0, 3)]
+        );
+        assert_eq!(
+            leaderboard(&db, Some(&["student"]), None).await,
+            [(b, 750, 1), (c, 0, 2)]
+        );
+        assert_eq!(
+            leaderboard(&db, Some(&["open"]), None).await,
+            [(b, 750, 1), (a, 350, 2), (c, 0, 3)]
+        );
+        // a's first blood puts it ahead on web alone
+        assert_eq!(
+            leaderboard(&db, None, Some("web")).await,
+            [(a, 350, 1), (b, 300, 2), (c, 0, 3)]
+        );
+
+        // c's earlier first blood on pwn ties it with a, and it got there first
+        solve(&db, c, pwn, 0).await;
+        assert_eq!(
+            leaderboard(&db, None, None).await,
+            [(b, 600, 1), (c, 350, 2), (a, 350, 3)]
+        );
+    }
+}
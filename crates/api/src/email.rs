@@ -20,10 +20,17 @@ pub struct PendingEmailUpdate {
     pub new_email: String,
 }
 
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct PendingUserVerification {
+    pub name: String,
+    pub email: String,
+}
+
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub enum PendingVerification {
     Team(PendingTeamVerification),
     EmailUpdate(PendingEmailUpdate),
+    User(PendingUserVerification),
 }
 
 pub struct EmailService {
@@ -175,6 +182,67 @@ impl EmailService {
         }
     }
 
+    pub async fn send_user_verification_email(
+        &self,
+        event: &Event,
+        pending_user_data: PendingUserVerification,
+    ) -> Result<()> {
+        let verification_token = nanoid!();
+        let to_email_addr = pending_user_data.email.clone();
+        let name = pending_user_data.name.clone();
+
+        {
+            let mut tokens_cache = self.verification_tokens.lock().unwrap();
+            tokens_cache.cache_set(
+                verification_token.clone(),
+                PendingVerification::User(pending_user_data),
+            );
+        }
+
+        let verification_link =
+            format!("{}/verify?token={}", self.app_base_url, verification_token);
+
+        let subject = format!("Verify your email for {}", event.name);
+        let body = format!(
+            "Hello {},\n\nPlease click the link below to finish creating your account for {}:\n{}\n\nThis link will expire in approximately 10 minutes.\n\nIf you did not request this, please ignore this email.",
+            name,
+            event.name,
+            verification_link
+        );
+
+        self.send_email(&to_email_addr, &subject, &body).await
+    }
+
+    pub async fn consume_pending_user_verification(
+        &self,
+        token: &str,
+    ) -> Result<PendingUserVerification> {
+        let mut tokens_cache = self.verification_tokens.lock().unwrap();
+        match tokens_cache.cache_remove(token) {
+            Some(PendingVerification::User(data)) => Ok(data),
+            Some(_) => Err(crate::error::Error::InvalidToken),
+            None => Err(crate::error::Error::InvalidToken),
+        }
+    }
+
+    pub async fn send_user_token_email(
+        &self,
+        event: &Event,
+        to_email: &str,
+        name: &str,
+        token: &str,
+    ) -> Result<()> {
+        let subject = format!("Your login token for {}", event.name);
+        let body = format!(
+            "Hello {},\n\nHere is your personal token for logging into {}:\n{}\n\nPlease keep it safe and do not share it with anyone, including your teammates.\n\nIf you did not request this, please ignore this email.",
+            name,
+            event.name,
+            token,
+        );
+
+        self.send_email(to_email, &subject, &body).await
+    }
+
     pub async fn send_resend_token_email(
         &self,
         event: &Event,
@@ -17,6 +17,10 @@ pub enum Error {
     Validation(#[from] validator::ValidationErrors),
     #[error("{0}")]
     Deploy(#[from] reqwest::Error), // TODO this might be used for other classes of error, idk yet
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Storage(eyre::Report),
     #[error("Invalid token")]
     InvalidToken,
     #[error("Challenge not found")]
@@ -25,14 +29,36 @@ pub enum Error {
     NotFoundTeam,
     #[error("Division not found")]
     NotFoundDivision,
+    #[error("Category not found")]
+    NotFoundCategory,
+    #[error("Attachment not found")]
+    NotFoundAttachment,
+    #[error("User not found")]
+    NotFoundUser,
     #[error("The event has not started, starts at {0}")]
     EventNotStarted(NaiveDateTime),
     #[error("The event has ended")]
     EventEnded,
     #[error("Wrong flag")]
     WrongFlag,
+    #[error("Challenge already solved")]
+    AlreadySolved,
     #[error("Team name already taken")]
     TeamNameTaken,
+    #[error("Email already taken")]
+    EmailTaken,
+    #[error("You are not in a team")]
+    NotInTeam,
+    #[error("You are already in a team")]
+    AlreadyInTeam,
+    #[error("Only the team captain can do that")]
+    NotCaptain,
+    #[error("Team is full")]
+    TeamFull,
+    #[error("Unsupported archive version {0}")]
+    ArchiveVersion(u32),
+    #[error("Archives can only be imported into an empty database")]
+    NotEmpty,
     #[error(
         "This is a generic error, you shouldn't recieve this is if you're a well behaved client!"
     )]
@@ -62,10 +88,15 @@ impl IntoResponse for Error {
             Error::Jwt(_) => (StatusCode::INTERNAL_SERVER_ERROR, "jwt_error"),
             Error::Validation(_) => (StatusCode::BAD_REQUEST, "validation_error"),
             Error::Deploy(_) => (StatusCode::BAD_REQUEST, "deploy_error"),
+            Error::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "io_error"),
+            Error::Storage(_) => (StatusCode::INTERNAL_SERVER_ERROR, "storage_error"),
             Error::InvalidToken => (StatusCode::UNAUTHORIZED, "invalid_token"),
-            Error::NotFoundChallenge | Error::NotFoundTeam | Error::NotFoundDivision => {
-                (StatusCode::NOT_FOUND, "not_found")
-            }
+            Error::NotFoundChallenge
+            | Error::NotFoundTeam
+            | Error::NotFoundDivision
+            | Error::NotFoundCategory
+            | Error::NotFoundAttachment
+            | Error::NotFoundUser => (StatusCode::NOT_FOUND, "not_found"),
             Error::EventNotStarted(start_time) => {
                 // Event not started special cased to return start time
                 return (
@@ -80,7 +111,15 @@ impl IntoResponse for Error {
             }
             Error::EventEnded => (StatusCode::UNAUTHORIZED, "event_ended"),
             Error::WrongFlag => (StatusCode::BAD_REQUEST, "wrong_flag"),
+            Error::AlreadySolved => (StatusCode::BAD_REQUEST, "already_solved"),
             Error::TeamNameTaken => (StatusCode::BAD_REQUEST, "team_name_taken"),
+            Error::EmailTaken => (StatusCode::BAD_REQUEST, "email_taken"),
+            Error::NotInTeam => (StatusCode::BAD_REQUEST, "not_in_team"),
+            Error::AlreadyInTeam => (StatusCode::BAD_REQUEST, "already_in_team"),
+            Error::NotCaptain => (StatusCode::FORBIDDEN, "not_captain"),
+            Error::TeamFull => (StatusCode::BAD_REQUEST, "team_full"),
+            Error::ArchiveVersion(_) => (StatusCode::BAD_REQUEST, "archive_version"),
+            Error::NotEmpty => (StatusCode::BAD_REQUEST, "not_empty"),
             Error::GenericError => (StatusCode::BAD_REQUEST, "generic_error"),
         };
 
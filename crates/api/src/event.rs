@@ -18,30 +18,149 @@ pub struct Event {
     pub end_time: NaiveDateTime,
     #[serde(default)]
     pub divisions: HashMap<String, String>,
+    // teams of the listed divisions also show up on the leaderboard of the key division,
+    // e.g. `open = ["college", "hs"]`
+    #[serde(default)]
+    pub division_includes: HashMap<String, Vec<String>>,
+    // only limits users joining through invites, team tokens can always be shared
+    #[serde(default)]
+    pub max_team_size: Option<i64>,
+    #[serde(default)]
+    pub scoring: Scoring,
 }
 
 impl Event {
     // NOTE(ani): ought to be AsRef<Path> but I don't care
     pub fn read_from_path(path: &str) -> eyre::Result<Self> {
         let cfg = fs::read_to_string(path)?;
-        Ok(toml::from_str(&cfg)?)
+        let event: Self = toml::from_str(&cfg)?;
+        for (division, includes) in &event.division_includes {
+            for d in std::iter::once(division).chain(includes) {
+                if !event.divisions.contains_key(d) {
+                    eyre::bail!("division_includes refers to unknown division {d}");
+                }
+            }
+        }
+        Ok(event)
+    }
+
+    /// Every division whose teams appear on `division`'s leaderboard, other than itself.
+    pub fn included_divisions(&self, division: &str) -> Vec<String> {
+        let mut included: Vec<String> = vec![];
+        let mut todo = vec![division];
+        while let Some(d) = todo.pop() {
+            for i in self.division_includes.get(d).into_iter().flatten() {
+                if i != division && !included.contains(i) {
+                    included.push(i.clone());
+                    todo.push(i);
+                }
+            }
+        }
+        included
     }
 }
 
-// ported from https://github.com/blueset/CTFd/blob/luna/CTFd/plugins/dynamic_challenges/decay.py#L72
-pub fn point_formula(points_min: i32, points_max: i32, solves: i32) -> i32 {
-    // note(jayden): ctfd has this configurable per chall, but here im hardcoding to 60 (same as used in sekaictf)
-    let decay = 100.0;
-    let gradient = 10.0;
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScoringFormula {
+    /// Always `points_max`.
+    Static,
+    /// CTFd style logarithmic decay.
+    #[default]
+    Ctfd,
+    /// Drops by the same amount each solve, reaching `points_min` after `decay` solves.
+    Linear,
+    /// rCTF's decay, where the points above `points_min` are halved after `decay + 1` solves.
+    Rctf,
+}
 
-    let _min = 1.0 + ((gradient - 1.0) / decay);
-    let x = 1.0 + ((gradient - 1.0) / decay) * solves as f64;
-    let ratio = (x / _min).ln() / (gradient / _min).ln();
-    let raw_score = (points_max as f64 - (points_max - points_min) as f64 * ratio).ceil();
-    let value = points_min.max(points_max.min(raw_score as i32));
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BloodBonusKind {
+    #[default]
+    Points,
+    /// Percent of the challenge's current points.
+    Percent,
+}
 
-    value
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Scoring {
+    #[serde(default)]
+    pub formula: ScoringFormula,
+    // challenges can override this with points.decay
+    #[serde(default = "default_decay")]
+    pub decay: f64,
+    // only used by the ctfd formula
+    #[serde(default = "default_gradient")]
+    pub gradient: f64,
+    // bonus for the first, second, ... solver of each challenge
+    #[serde(default)]
+    pub blood_bonus: Vec<i32>,
+    #[serde(default)]
+    pub blood_bonus_kind: BloodBonusKind,
+}
+
+fn default_decay() -> f64 {
+    100.0
+}
+
+fn default_gradient() -> f64 {
+    10.0
 }
+
+impl Default for Scoring {
+    fn default() -> Self {
+        Self {
+            formula: ScoringFormula::default(),
+            decay: default_decay(),
+            gradient: default_gradient(),
+            blood_bonus: vec![],
+            blood_bonus_kind: BloodBonusKind::default(),
+        }
+    }
+}
+
+impl Scoring {
+    pub fn points(&self, points_min: i32, points_max: i32, decay: Option<f64>, solves: i32) -> i32 {
+        let decay = decay.unwrap_or(self.decay).max(f64::EPSILON);
+        let (min, max, solves) = (points_min as f64, points_max as f64, solves as f64);
+
+        let raw_score = match self.formula {
+            ScoringFormula::Static => max,
+            // ported from https://github.com/blueset/CTFd/blob/luna/CTFd/plugins/dynamic_challenges/decay.py#L72
+            ScoringFormula::Ctfd => {
+                let gradient = self.gradient;
+                let _min = 1.0 + ((gradient - 1.0) / decay);
+                let x = 1.0 + ((gradient - 1.0) / decay) * solves;
+                let ratio = (x / _min).ln() / (gradient / _min).ln();
+                max - (max - min) * ratio
+            }
+            ScoringFormula::Linear => max - (max - min) * (solves / decay),
+            // https://github.com/redpwn/rctf/blob/master/server/util/scores.ts
+            ScoringFormula::Rctf => {
+                min + (max - min) / (1.0 + (solves - 1.0).max(0.0).powi(2) / decay.powi(2))
+            }
+        };
+        let rounded = match self.formula {
+            ScoringFormula::Rctf => raw_score.round(),
+            _ => raw_score.ceil(),
+        };
+
+        points_min.max(points_max.min(rounded as i32))
+    }
+
+    /// Blood bonus for the `place`th solve of a challenge worth `points`, like `scored_solves()`.
+    pub fn bonus(&self, place: i32, points: i32) -> i32 {
+        let Some(&bonus) = self.blood_bonus.get((place - 1) as usize) else {
+            return 0;
+        };
+        match self.blood_bonus_kind {
+            BloodBonusKind::Points => bonus,
+            BloodBonusKind::Percent => (points as f64 * bonus as f64 / 100.0).ceil() as i32,
+        }
+    }
+}
+
 /* web routes
 NOTE(ani): keep it small or move into a separate file */
 
@@ -52,3 +171,55 @@ async fn event_route(StateE(state): StateE<State>) -> Result<Json<Event>> {
 pub fn router() -> Router<crate::State> {
     Router::new().route("/", get(event_route))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scoring(formula: ScoringFormula) -> Scoring {
+        Scoring {
+            formula,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rctf() {
+        let scoring = scoring(ScoringFormula::Rctf);
+        // what rCTF gives a 100-500 challenge with 10 max solves
+        for (solves, points) in [(0, 500), (1, 500), (2, 496), (6, 420), (11, 300), (21, 180)] {
+            assert_eq!(
+                scoring.points(100, 500, Some(10.0), solves),
+                points,
+                "{solves}"
+            );
+        }
+        assert_eq!(scoring.points(100, 500, Some(10.0), 10_000), 100);
+    }
+
+    #[test]
+    fn linear() {
+        let scoring = scoring(ScoringFormula::Linear);
+        assert_eq!(scoring.points(100, 500, Some(4.0), 0), 500);
+        assert_eq!(scoring.points(100, 500, Some(4.0), 1), 400);
+        assert_eq!(scoring.points(100, 500, Some(4.0), 4), 100);
+        assert_eq!(scoring.points(100, 500, Some(4.0), 8), 100);
+    }
+
+    #[test]
+    fn blood_bonus() {
+        let scoring = Scoring {
+            blood_bonus: vec![30, 20],
+            ..Default::default()
+        };
+        assert_eq!(scoring.bonus(1, 500), 30);
+        assert_eq!(scoring.bonus(3, 500), 0);
+
+        let scoring = Scoring {
+            blood_bonus: vec![10],
+            blood_bonus_kind: BloodBonusKind::Percent,
+            ..Default::default()
+        };
+        assert_eq!(scoring.bonus(1, 455), 46);
+    }
+}
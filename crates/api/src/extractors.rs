@@ -1,8 +1,10 @@
 use std::ops::Deref;
 
 use crate::{
+    api::users::team_of,
     config::Config,
     jwt::{decode_jwt, Claims},
+    DB,
 };
 use axum::{
     extract::{FromRequestParts, OptionalFromRequestParts},
@@ -11,21 +13,32 @@ use axum::{
 };
 use axum_extra::extract::CookieJar;
 
+fn claims<S: AsRef<Config>>(jar: &CookieJar, state: &S) -> crate::Result<Claims> {
+    let jwt = jar.get("token").ok_or(crate::Error::InvalidToken)?.value();
+    decode_jwt(&state.as_ref().jwt_keys, jwt)
+}
+
+/// A team token, or a user token of someone in a team. For users, `team_id` is their current team,
+/// so leaving or getting kicked takes effect right away.
 #[derive(Debug, Clone)]
 pub struct Auth(pub Claims);
 
 impl<S, B> FromRequestParts<B> for Auth
 where
     B: Deref<Target = S> + Send + Sync,
-    S: AsRef<Config> + Send + Sync,
+    S: AsRef<Config> + AsRef<DB> + Send + Sync,
 {
     type Rejection = crate::Error;
 
     async fn from_request_parts(parts: &mut Parts, cfg: &B) -> Result<Self, Self::Rejection> {
         let jar = parts.extract::<CookieJar>().await.unwrap(); //infailable
-        let jwt = jar.get("token").ok_or(crate::Error::InvalidToken)?.value();
+        let mut claims = claims(&jar, &**cfg)?;
 
-        let claims = decode_jwt(&cfg.as_ref().jwt_keys, jwt)?;
+        if let Some(user_id) = &claims.user_id {
+            claims.team_id = team_of((**cfg).as_ref(), user_id)
+                .await?
+                .ok_or(crate::Error::NotInTeam)?;
+        }
 
         Ok(Auth(claims))
     }
@@ -34,7 +47,7 @@ where
 impl<S, B> OptionalFromRequestParts<B> for Auth
 where
     B: Deref<Target = S> + Send + Sync,
-    S: AsRef<Config> + Send + Sync,
+    S: AsRef<Config> + AsRef<DB> + Send + Sync,
 {
     type Rejection = crate::Error;
 
@@ -45,13 +58,33 @@ where
         match <Auth as FromRequestParts<B>>::from_request_parts(parts, cfg).await {
             Ok(a) => Ok(Some(a)),
             Err(e) => match e {
-                crate::Error::InvalidToken => Ok(None),
+                crate::Error::InvalidToken | crate::Error::NotInTeam => Ok(None),
                 _ => Err(e),
             },
         }
     }
 }
 
+/// A user token, whether or not the user is in a team. Holds the user's public id.
+#[derive(Debug, Clone)]
+pub struct UserAuth(pub String);
+
+impl<S, B> FromRequestParts<B> for UserAuth
+where
+    B: Deref<Target = S> + Send + Sync,
+    S: AsRef<Config> + Send + Sync,
+{
+    type Rejection = crate::Error;
+
+    async fn from_request_parts(parts: &mut Parts, cfg: &B) -> Result<Self, Self::Rejection> {
+        let jar = parts.extract::<CookieJar>().await.unwrap(); //infailable
+        claims(&jar, &**cfg)?
+            .user_id
+            .map(UserAuth)
+            .ok_or(crate::Error::InvalidToken)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Admin;
 
@@ -69,7 +102,7 @@ where
             .ok_or(crate::Error::InvalidToken)?
             .value();
 
-        if token == cfg.as_ref().admin_token {
+        if token == AsRef::<Config>::as_ref(&**cfg).admin_token {
             Ok(Admin)
         } else {
             Err(crate::Error::InvalidToken)
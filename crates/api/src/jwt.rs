@@ -6,9 +6,13 @@ use crate::config;
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub exp: u64,
+    // empty in user tokens, `Auth` fills in the user's current team
     pub team_id: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ethereal: Option<bool>,
+    // missing in team tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
 }
 
 impl Claims {
@@ -28,6 +32,24 @@ pub fn generate_jwt(
             team_id: team_id.to_string(),
             exp: jsonwebtoken::get_current_timestamp() + exp.num_seconds() as u64,
             ethereal: None,
+            user_id: None,
+        },
+        &keys.encoding,
+    )
+}
+
+pub fn generate_user_jwt(
+    keys: &config::JwtKeys,
+    user_id: &str,
+    exp: chrono::Duration,
+) -> Result<String> {
+    jsonwebtoken::encode(
+        &Default::default(),
+        &Claims {
+            team_id: String::new(),
+            exp: jsonwebtoken::get_current_timestamp() + exp.num_seconds() as u64,
+            ethereal: None,
+            user_id: Some(user_id.to_string()),
         },
         &keys.encoding,
     )
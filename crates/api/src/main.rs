@@ -1,11 +1,13 @@
 use axum::{http::HeaderValue, Router};
+use deployer_common::storage::Storage;
 use envconfig::Envconfig;
-use eyre::Context;
+use eyre::{eyre, Context};
 use sqlx::postgres::PgPoolOptions;
 use tower_http::cors::{Any, CorsLayer};
 
 mod admin;
 mod api;
+mod attachments;
 mod badges;
 mod config;
 mod db;
@@ -14,6 +16,7 @@ mod error;
 mod event;
 mod extractors;
 mod jwt;
+mod releases;
 
 use config::State;
 use db::DB;
@@ -36,20 +39,47 @@ async fn main() -> eyre::Result<()> {
 
     sqlx::migrate!("../../migrations").run(&pool).await?;
 
+    // the scoring config might have changed since points were last cached
+    db::sync_blood_bonuses(&pool, &event.scoring).await?;
+    db::sync_division_includes(&pool, &event).await?;
+    let chall_ids: Vec<i32> = sqlx::query_scalar("SELECT id FROM challenges")
+        .fetch_all(&pool)
+        .await?;
+    for chall_id in chall_ids {
+        db::update_chall_cache(&pool, &event.scoring, chall_id).await?;
+    }
+
     let cors = CorsLayer::new()
         .allow_methods(Any)
         .allow_origin([cfg.cors_origin.parse::<HeaderValue>().unwrap()])
         .allow_headers(Any);
     // .allow_credentials(true);
 
+    let attachment_storage = match &cfg.attachments_storage {
+        Some(kind) => Some(
+            Storage::remote_from_env(kind)
+                .await?
+                .ok_or_else(|| eyre!("Unknown ATTACHMENTS_STORAGE {kind}, expected gcs or s3"))?,
+        ),
+        None => None,
+    };
+
+    let state = State::new(config::StateInner {
+        email: email::EmailService::new(&cfg),
+        attachment_storage,
+        history_cache: Default::default(),
+        config: cfg,
+        event,
+        db: pool,
+    });
+
+    if let Some(url) = state.config.release_webhook_url.clone() {
+        tokio::spawn(releases::announce_task(state.clone(), url));
+    }
+
     let app = Router::<State>::new()
         .nest("/api", api::router())
-        .with_state(State::new(config::StateInner {
-            email: email::EmailService::new(&cfg),
-            config: cfg,
-            event,
-            db: pool,
-        }))
+        .with_state(state)
         .layer(cors);
 
     // run our app with hyper, listening globally on port 3333
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::{Result, State};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize)]
+struct WebhookData {
+    content: String,
+    embeds: Option<()>,
+    attachments: Vec<()>,
+}
+
+/// Posts to the release webhook whenever a wave of scheduled challenges is released. Challenges
+/// released before the event starts are part of the start and aren't announced.
+pub async fn announce_task(state: State, webhook_url: String) {
+    let client = match reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            log::error!("Failed to create release webhook client: {}", e);
+            return;
+        }
+    };
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+        if let Err(e) = announce(&state, &client, &webhook_url).await {
+            log::error!("Failed to announce released challenges: {}", e);
+        }
+    }
+}
+
+async fn announce(state: &State, client: &reqwest::Client, webhook_url: &str) -> Result<()> {
+    // claimed on its own so the row locks aren't held while the webhook is slow
+    let released = sqlx::query!(
+        r#"UPDATE challenges c SET release_announced = true
+        FROM categories
+        WHERE categories.id = c.category_id
+            AND c.visible
+            AND NOT c.release_announced
+            AND c.release_at > $1
+            AND c.release_at <= $2
+        RETURNING c.id, c.name, categories.name AS category"#,
+        state.event.start_time,
+        Utc::now().naive_utc(),
+    )
+    .fetch_all(&state.db)
+    .await?;
+    if released.is_empty() {
+        return Ok(());
+    }
+
+    let names: Vec<String> = released
+        .iter()
+        .map(|c| format!("`{}/{}`", c.category, c.name))
+        .collect();
+    let sent = client
+        .post(webhook_url)
+        .json(&WebhookData {
+            content: format!("New challenges released: {}", names.join(", ")),
+            embeds: None,
+            attachments: Vec::new(),
+        })
+        .send()
+        .await
+        .and_then(|res| res.error_for_status());
+
+    if let Err(e) = sent {
+        // unclaimed so the next check tries again
+        let ids: Vec<i32> = released.iter().map(|c| c.id).collect();
+        sqlx::query!(
+            "UPDATE challenges SET release_announced = false WHERE id = ANY($1)",
+            &ids
+        )
+        .execute(&state.db)
+        .await?;
+        return Err(e.into());
+    }
+    log::info!("Announced {} released challenge(s)", released.len());
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use std::{collections::HashMap, fmt::Debug};
+
+use chrono::NaiveDateTime;
+use deployer_common::challenge::DeploymentStrategy;
+use serde::Deserialize;
+use serde_json::Value;
+
+// also used for groups
+#[derive(Deserialize)]
+pub struct PlatformCategory {
+    pub name: String,
+}
+
+/// Challenge as returned by `GET /api/admin/challs`.
+#[derive(Deserialize)]
+pub struct PlatformChallenge {
+    pub public_id: String,
+    pub name: String,
+    pub author: String,
+    pub description: String,
+    pub points_min: i32,
+    pub points_max: i32,
+    pub points_decay: Option<f64>,
+    pub flag: String,
+    pub attachments: Value,
+    pub strategy: DeploymentStrategy,
+    pub visible: bool,
+    pub category: PlatformCategory,
+    pub group: Option<PlatformCategory>,
+    pub difficulty: Option<String>,
+    pub tags: Vec<String>,
+    pub release_at: Option<NaiveDateTime>,
+}
+
+/// The parts of a challenge that `platform update` sets.
+pub struct ChallengeFields {
+    pub name: String,
+    pub author: String,
+    pub description: String,
+    pub points_min: i32,
+    pub points_max: i32,
+    pub points_decay: Option<f64>,
+    pub flag: String,
+    pub strategy: DeploymentStrategy,
+    pub visible: bool,
+    pub category: String,
+    pub group: Option<String>,
+    pub difficulty: Option<String>,
+    pub release_at: Option<NaiveDateTime>,
+    // sorted
+    pub tags: Vec<String>,
+    // file name to sha256, or to the url for url attachments
+    pub attachments: HashMap<String, String>,
+}
+
+impl From<PlatformChallenge> for ChallengeFields {
+    fn from(c: PlatformChallenge) -> Self {
+        let attachments = match c.attachments {
+            Value::Object(map) => map
+                .into_iter()
+                .filter_map(|(name, a)| {
+                    let digest = match a {
+                        // legacy attachments are just the url
+                        Value::String(url) => url,
+                        a => a
+                            .get("sha256")
+                            .or_else(|| a.get("url"))?
+                            .as_str()?
+                            .to_owned(),
+                    };
+                    Some((name, digest))
+                })
+                .collect(),
+            _ => HashMap::new(),
+        };
+
+        Self {
+            name: c.name,
+            author: c.author,
+            description: c.description,
+            points_min: c.points_min,
+            points_max: c.points_max,
+            points_decay: c.points_decay,
+            flag: c.flag,
+            strategy: c.strategy,
+            visible: c.visible,
+            category: c.category.name,
+            group: c.group.map(|g| g.name),
+            difficulty: c.difficulty,
+            release_at: c.release_at,
+            tags: c.tags,
+            attachments,
+        }
+    }
+}
+
+fn changed<T: PartialEq + Debug>(lines: &mut Vec<String>, field: &str, old: &T, new: &T) {
+    if old != new {
+        lines.push(format!("{field}: {old:?} -> {new:?}"));
+    }
+}
+
+/// Human readable list of what changes from `old` to `new`, empty if nothing does.
+pub fn diff_fields(old: &ChallengeFields, new: &ChallengeFields) -> Vec<String> {
+    let mut lines = vec![];
+    changed(&mut lines, "name", &old.name, &new.name);
+    changed(&mut lines, "author", &old.author, &new.author);
+    changed(&mut lines, "category", &old.category, &new.category);
+    changed(&mut lines, "group", &old.group, &new.group);
+    changed(&mut lines, "difficulty", &old.difficulty, &new.difficulty);
+    changed(&mut lines, "tags", &old.tags, &new.tags);
+    changed(&mut lines, "release_at", &old.release_at, &new.release_at);
+    changed(&mut lines, "points_min", &old.points_min, &new.points_min);
+    changed(&mut lines, "points_max", &old.points_max, &new.points_max);
+    changed(
+        &mut lines,
+        "points_decay",
+        &old.points_decay,
+        &new.points_decay,
+    );
+    changed(&mut lines, "flag", &old.flag, &new.flag);
+    changed(&mut lines, "strategy", &old.strategy, &new.strategy);
+    changed(&mut lines, "visible", &old.visible, &new.visible);
+    // descriptions are long, printing both isn't useful
+    if old.description != new.description {
+        lines.push(format!(
+            "description: changed ({} -> {} chars)",
+            old.description.chars().count(),
+            new.description.chars().count()
+        ));
+    }
+
+    let mut names: Vec<_> = old
+        .attachments
+        .keys()
+        .chain(new.attachments.keys())
+        .collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        match (old.attachments.get(name), new.attachments.get(name)) {
+            (None, Some(_)) => lines.push(format!("attachment {name}: added")),
+            (Some(_), None) => lines.push(format!("attachment {name}: removed")),
+            (Some(o), Some(n)) if o != n => lines.push(format!("attachment {name}: changed")),
+            _ => {}
+        }
+    }
+
+    lines
+}
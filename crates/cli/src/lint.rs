@@ -0,0 +1,488 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use deployer_common::challenge::{Attachment, DeployableChallenge, ExposeType, Flag};
+use eyre::{Context, Result};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    // the challenge.toml the issue is in
+    pub path: PathBuf,
+    pub id: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+// just the parts of the deployer's HOST_KEYCHAINS file that matter here
+#[derive(Deserialize)]
+struct HostKeychain {
+    id: String,
+    caddy: Option<CaddyKeychain>,
+}
+
+#[derive(Deserialize)]
+struct CaddyKeychain {
+    endpoint: String,
+    base: String,
+}
+
+pub struct Linter {
+    /// Host ids mapped to whether they have caddy configured. `None` skips host checks.
+    hosts: Option<HashMap<String, bool>>,
+    issues: Vec<Issue>,
+}
+
+impl Linter {
+    pub fn new(keychain: Option<&Path>) -> Result<Self> {
+        let hosts = match keychain {
+            Some(path) => {
+                let contents = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read keychain {}", path.display()))?;
+                let chains: Vec<HostKeychain> = serde_json::from_str(&contents)?;
+                Some(
+                    chains
+                        .into_iter()
+                        .map(|c| {
+                            let has_caddy = c
+                                .caddy
+                                .is_some_and(|c| !c.endpoint.is_empty() && !c.base.is_empty());
+                            (c.id, has_caddy)
+                        })
+                        .collect(),
+                )
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            hosts,
+            issues: vec![],
+        })
+    }
+
+    fn push(&mut self, severity: Severity, root: &Path, id: Option<&str>, message: String) {
+        self.issues.push(Issue {
+            severity,
+            path: root.join("challenge.toml"),
+            id: id.map(ToOwned::to_owned),
+            message,
+        });
+    }
+
+    /// Lints every challenge under `roots`, including checks across challenges.
+    pub fn lint(mut self, roots: &[PathBuf]) -> Vec<Issue> {
+        let mut challs = vec![];
+        for root in roots {
+            match DeployableChallenge::from_root(root.clone()) {
+                Ok(dc) => challs.push(dc),
+                Err(e) => self.push(Severity::Error, root, None, format!("{e:#}")),
+            }
+        }
+
+        for dc in &challs {
+            self.lint_chall(dc);
+        }
+
+        // the platform has UNIQUE constraints on both of these
+        let mut ids: HashMap<&str, &Path> = HashMap::new();
+        let mut names: HashMap<(&str, &str), &Path> = HashMap::new();
+        for dc in &challs {
+            let chall = &dc.chall;
+            if let Some(other) = ids.insert(&chall.id, &dc.root) {
+                let message = format!("Duplicate id, also used in {}", other.display());
+                self.push(Severity::Error, &dc.root, Some(&chall.id), message);
+            }
+            if let Some(other) = names.insert((&chall.name, &chall.category), &dc.root) {
+                let message = format!(
+                    "Duplicate name {:?} in category {:?}, also used in {}",
+                    chall.name,
+                    chall.category,
+                    other.display()
+                );
+                self.push(Severity::Error, &dc.root, Some(&chall.id), message);
+            }
+        }
+
+        self.issues
+    }
+
+    fn lint_chall(&mut self, dc: &DeployableChallenge) {
+        let DeployableChallenge { chall, root } = dc;
+        let mut found = vec![];
+        let mut error = |message: String| found.push((Severity::Error, message));
+
+        if chall.points.min > chall.points.max {
+            error(format!(
+                "points.min ({}) is greater than points.max ({})",
+                chall.points.min, chall.points.max
+            ));
+        }
+
+        if chall.points.decay.is_some_and(|d| d <= 0.0) {
+            error("points.decay must be positive".to_owned());
+        }
+
+        match &chall.flag {
+            Flag::Raw(flag) if flag.trim().is_empty() => error("Flag is empty".to_owned()),
+            Flag::Raw(_) => {}
+            Flag::File { file } => match fs::read_to_string(root.join(file)) {
+                Ok(flag) if flag.trim().is_empty() => {
+                    error(format!("Flag file {} is empty", file.display()))
+                }
+                Ok(_) => {}
+                Err(e) => error(format!("Failed to read flag file {}: {e}", file.display())),
+            },
+        }
+
+        for attachment in chall.provide.iter().flatten() {
+            match attachment {
+                Attachment::File(file) | Attachment::Named { file, .. } => {
+                    if !root.join(file).is_file() {
+                        error(format!("Attachment {} does not exist", file.display()));
+                    }
+                }
+                Attachment::Archive { dir, .. } => {
+                    if !root.join(dir).is_dir() {
+                        error(format!("Attachment dir {} does not exist", dir.display()));
+                    }
+                }
+                Attachment::Url { url, .. } => {
+                    if !Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https")) {
+                        error(format!("Attachment url {url} is not a valid http(s) url"));
+                    }
+                }
+                Attachment::Generated { build, path, .. } => {
+                    if !root.join(build).join("Dockerfile").is_file() {
+                        error(format!(
+                            "Generated attachment build context {} has no Dockerfile",
+                            build.display()
+                        ));
+                    }
+                    if !path.starts_with('/') {
+                        error(format!("Generated attachment path {path} must be absolute"));
+                    }
+                }
+            }
+            if let Err(e) = attachment.file_name() {
+                error(format!("{e}"));
+            }
+        }
+
+        if let Some(solve) = &chall.solve {
+            if !root.join(&solve.build).join("Dockerfile").is_file() {
+                error(format!(
+                    "Solve build context {} has no Dockerfile",
+                    solve.build.display()
+                ));
+            }
+            if chall.container.is_none() {
+                error("Challenge has a solve but no containers to run it against".to_owned());
+            }
+            if chall
+                .container
+                .as_ref()
+                .is_some_and(|c| c.contains_key("solve"))
+            {
+                error("Container solve has the same image as the solver, rename it".to_owned());
+            }
+        }
+
+        let host = chall.host.as_deref().unwrap_or("default");
+        let host_caddy = self.hosts.as_ref().map(|h| h.get(host).copied());
+        if host_caddy == Some(None) {
+            error(format!("Host {host} is not in the keychain"));
+        }
+
+        // sorted so the output is stable
+        let mut containers: Vec<_> = chall.container.iter().flatten().collect();
+        containers.sort_by_key(|(name, _)| *name);
+        for (name, ct) in containers {
+            if !root.join(&ct.build).join("Dockerfile").is_file() {
+                error(format!(
+                    "Container {name} build context {} has no Dockerfile",
+                    ct.build.display()
+                ));
+            }
+
+            let mut expose: Vec<_> = ct.expose.iter().flatten().collect();
+            expose.sort_by_key(|(port, _)| **port);
+            for (port, expose) in expose {
+                if *port == 0 {
+                    error(format!("Container {name} exposes invalid port 0"));
+                }
+                if matches!(expose, ExposeType::Http) && host_caddy == Some(Some(false)) {
+                    error(format!(
+                        "Container {name} exposes http port {port}, but host {host} has no caddy config"
+                    ));
+                }
+            }
+        }
+
+        if let Some(provide) = &chall.provide {
+            for file in provide.iter().filter_map(|a| match a {
+                Attachment::File(file) => Some(file),
+                _ => None,
+            }) {
+                found.push((
+                    Severity::Warning,
+                    format!(
+                        "Attachment {} uses the deprecated string form, use [[provide]] file = ... instead",
+                        file.display()
+                    ),
+                ));
+            }
+        }
+
+        for (severity, message) in found {
+            self.push(severity, root, Some(&chall.id), message);
+        }
+    }
+}
+
+pub fn print_issues(issues: &[Issue], format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Text => {
+            for issue in issues {
+                let severity = match issue.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                };
+                match &issue.id {
+                    Some(id) => println!(
+                        "{severity}: [{id}] {} ({})",
+                        issue.message,
+                        issue.path.display()
+                    ),
+                    None => println!("{severity}: {} ({})", issue.message, issue.path.display()),
+                }
+            }
+
+            let errors = issues
+                .iter()
+                .filter(|i| i.severity == Severity::Error)
+                .count();
+            println!("{errors} error(s), {} warning(s)", issues.len() - errors);
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(issues)?),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a fresh directory under the temp dir with `files` in it
+    fn dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nerine-lint-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn chall_toml(id: &str, rest: &str) -> String {
+        format!(
+            r#"
+            id = "{id}"
+            name = "{id}"
+            author = "author"
+            description = ""
+            category = "web"
+            {rest}
+            "#
+        )
+    }
+
+    fn messages(issues: &[Issue]) -> Vec<(Severity, &str)> {
+        issues
+            .iter()
+            .map(|i| (i.severity, i.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn clean() {
+        let root = dir(
+            "clean",
+            &[
+                (
+                    "challenge.toml",
+                    &chall_toml(
+                        "clean",
+                        r#"
+                        flag = { file = "flag.txt" }
+                        points = { min = 100, max = 500 }
+                        provide = [{ file = "dist/chall", as = "chall" }]
+                        container.default = { build = "src", expose = { 1337 = "tcp" } }
+                        "#,
+                    ),
+                ),
+                ("flag.txt", "flag{clean}\n"),
+                ("dist/chall", "binary"),
+                ("src/Dockerfile", "FROM scratch"),
+            ],
+        );
+        let issues = Linter::new(None).unwrap().lint(std::slice::from_ref(&root));
+        assert!(issues.is_empty(), "{issues:?}");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn challenge_issues() {
+        let root = dir(
+            "issues",
+            &[
+                (
+                    "challenge.toml",
+                    &chall_toml(
+                        "issues",
+                        r#"
+                        flag = { file = "flag.txt" }
+                        points = { min = 500, max = 100 }
+                        provide = ["missing.zip"]
+                        container.default = { build = "src", expose = { 0 = "tcp" } }
+                        "#,
+                    ),
+                ),
+                ("flag.txt", " \n"),
+            ],
+        );
+        let issues = Linter::new(None).unwrap().lint(std::slice::from_ref(&root));
+        assert_eq!(
+            messages(&issues),
+            [
+                (
+                    Severity::Error,
+                    "points.min (500) is greater than points.max (100)"
+                ),
+                (Severity::Error, "Flag file flag.txt is empty"),
+                (Severity::Error, "Attachment missing.zip does not exist"),
+                (
+                    Severity::Error,
+                    "Container default build context src has no Dockerfile"
+                ),
+                (Severity::Error, "Container default exposes invalid port 0"),
+                (
+                    Severity::Warning,
+                    "Attachment missing.zip uses the deprecated string form, use [[provide]] file = ... instead"
+                ),
+            ]
+        );
+        assert!(issues.iter().all(|i| i.id.as_deref() == Some("issues")));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn duplicates_and_parse_errors() {
+        let chall = chall_toml("dup", r#"flag = "flag{dup}""#) + "points = { min = 1, max = 1 }";
+        let root = dir(
+            "duplicates",
+            &[
+                ("a/challenge.toml", &chall),
+                ("b/challenge.toml", &chall),
+                ("c/challenge.toml", "id = "),
+            ],
+        );
+        let roots = ["a", "b", "c"].map(|d| root.join(d));
+        let issues = Linter::new(None).unwrap().lint(&roots);
+
+        assert_eq!(issues.len(), 3, "{issues:?}");
+        assert_eq!(issues[0].path, root.join("c/challenge.toml"));
+        assert_eq!(issues[0].id, None);
+        for issue in &issues[1..] {
+            assert_eq!(issue.path, root.join("b/challenge.toml"));
+            assert!(issue.message.starts_with("Duplicate"), "{issue:?}");
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn hosts() {
+        let root = dir(
+            "hosts",
+            &[
+                (
+                    "keychain.json",
+                    r#"[{ "id": "default" }, { "id": "web", "caddy": { "endpoint": "http://caddy:2019", "base": "ctf.example.com" } }]"#,
+                ),
+                (
+                    "plain/challenge.toml",
+                    &chall_toml(
+                        "plain",
+                        r#"
+                        flag = "flag{plain}"
+                        points = { min = 1, max = 1 }
+                        container.default = { build = ".", expose = { 80 = "http" } }
+                        "#,
+                    ),
+                ),
+                ("plain/Dockerfile", "FROM scratch"),
+                (
+                    "caddy/challenge.toml",
+                    &chall_toml(
+                        "caddy",
+                        r#"
+                        flag = "flag{caddy}"
+                        points = { min = 1, max = 1 }
+                        host = "web"
+                        container.default = { build = ".", expose = { 80 = "http" } }
+                        "#,
+                    ),
+                ),
+                ("caddy/Dockerfile", "FROM scratch"),
+                (
+                    "unknown/challenge.toml",
+                    &chall_toml(
+                        "unknown",
+                        r#"
+                        flag = "flag{unknown}"
+                        points = { min = 1, max = 1 }
+                        host = "gone"
+                        "#,
+                    ),
+                ),
+            ],
+        );
+        let roots = ["plain", "caddy", "unknown"].map(|d| root.join(d));
+        let issues = Linter::new(Some(&root.join("keychain.json")))
+            .unwrap()
+            .lint(&roots);
+
+        assert_eq!(
+            messages(&issues),
+            [
+                (
+                    Severity::Error,
+                    "Container default exposes http port 80, but host default has no caddy config"
+                ),
+                (Severity::Error, "Host gone is not in the keychain"),
+            ]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use bollard::{
+    query_parameters::{
+        CreateContainerOptionsBuilder, InspectContainerOptions, ListContainersOptionsBuilder,
+        RemoveContainerOptionsBuilder, StartContainerOptions,
+    },
+    secret::NetworkCreateRequest,
+};
+use deployer_common::{
+    challenge::{DeployableChallenge, DeployableContext, ExposeType},
+    deploy::container_body,
+};
+use eyre::{Context, Result, eyre};
+
+// never the names the deployer uses, so running locally on a deployer host can't touch the
+// live deployment
+fn local_network_name(chall_id: &str) -> String {
+    format!("{}-local-network", chall_id)
+}
+
+fn local_container_name(chall_id: &str, ct: &str) -> String {
+    format!("{}-local-container-{}", chall_id, ct)
+}
+
+/// A challenge started on the local docker daemon, laid out like a static deployment.
+pub struct LocalDeployment {
+    pub network_name: String,
+    pub containers: Vec<String>,
+}
+
+/// Starts every container of the challenge. There is no caddy locally, so http ports are
+/// bound to host ports like tcp ones. Host ports are picked by docker, the static deployment
+/// ones may already be taken.
+pub async fn start(ctx: &DeployableContext, dc: &DeployableChallenge) -> Result<LocalDeployment> {
+    let chall = &dc.chall;
+    let Some(chall_containers) = &chall.container else {
+        return Err(eyre!("Challenge {} does not have a container", chall.id));
+    };
+
+    let network_name = local_network_name(&chall.id);
+    // the network can't be removed while a crashed or interrupted run still has containers on it
+    remove_stale_containers(ctx, &chall.id, &network_name).await?;
+    ctx.docker.remove_network(&network_name).await.ok();
+    ctx.docker
+        .create_network(NetworkCreateRequest {
+            name: network_name.clone(),
+            ..Default::default()
+        })
+        .await?;
+
+    let mut deployment = LocalDeployment {
+        network_name,
+        containers: vec![],
+    };
+    if let Err(e) = start_containers(ctx, dc, chall_containers.keys(), &mut deployment).await {
+        deployment.stop(ctx).await;
+        return Err(e);
+    }
+
+    Ok(deployment)
+}
+
+async fn start_containers(
+    ctx: &DeployableContext,
+    dc: &DeployableChallenge,
+    cts: impl Iterator<Item = &String>,
+    deployment: &mut LocalDeployment,
+) -> Result<()> {
+    let chall = &dc.chall;
+    let mut cts: Vec<_> = cts.collect();
+    cts.sort();
+
+    for ct in cts {
+        let container_name = local_container_name(&chall.id, ct);
+        let chall_container = &chall.container.as_ref().unwrap()[ct];
+
+        let mut exposed: Vec<_> = chall_container.expose.iter().flatten().collect();
+        exposed.sort_by_key(|(p, _)| **p);
+        // 0 lets docker pick a free port
+        let ports: HashMap<u16, u16> = exposed.iter().map(|(p, _)| (**p, 0)).collect();
+
+        remove_container(ctx, &container_name).await;
+        ctx.docker
+            .create_container(
+                Some(
+                    CreateContainerOptionsBuilder::new()
+                        .name(&container_name)
+                        .build(),
+                ),
+                container_body(chall, ctx, ct, &deployment.network_name, &ports),
+            )
+            .await
+            .with_context(|| format!("Failed to create container {ct}"))?;
+        deployment.containers.push(container_name.clone());
+
+        ctx.docker
+            .start_container(&container_name, None::<StartContainerOptions>)
+            .await
+            .with_context(|| format!("Failed to start container {ct}"))?;
+
+        let bound = ctx
+            .docker
+            .inspect_container(&container_name, None::<InspectContainerOptions>)
+            .await?
+            .network_settings
+            .and_then(|n| n.ports)
+            .unwrap_or_default();
+        for (p, t) in exposed {
+            let host_port = bound
+                .get(&format!("{p}/tcp"))
+                .and_then(|b| b.as_ref()?.first()?.host_port.clone())
+                .ok_or_else(|| eyre!("Port {p} of container {ct} was not bound"))?;
+            match t {
+                ExposeType::Tcp => println!("[{}/{ct}] {p}: nc localhost {host_port}", chall.id),
+                ExposeType::Http => {
+                    println!("[{}/{ct}] {p}: http://localhost:{host_port}", chall.id)
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the containers a previous run of the challenge left behind, including the solver's.
+async fn remove_stale_containers(
+    ctx: &DeployableContext,
+    chall_id: &str,
+    network_name: &str,
+) -> Result<()> {
+    let prefix = format!("/{}", local_container_name(chall_id, ""));
+    let solver = format!("/{network_name}-solve");
+    // docker matches names as a regex anywhere in the name, so this only narrows it down
+    let filters = HashMap::from([("name", vec![format!("{chall_id}-local-")])]);
+    let containers = ctx
+        .docker
+        .list_containers(Some(
+            ListContainersOptionsBuilder::new()
+                .all(true)
+                .filters(&filters)
+                .build(),
+        ))
+        .await
+        .context("Failed to list leftover local containers")?;
+
+    for name in containers
+        .into_iter()
+        .flat_map(|c| c.names.unwrap_or_default())
+    {
+        if name.starts_with(&prefix) || name == solver {
+            remove_container(ctx, &name[1..]).await;
+        }
+    }
+
+    Ok(())
+}
+
+async fn remove_container(ctx: &DeployableContext, name: &str) {
+    ctx.docker
+        .remove_container(
+            name,
+            Some(
+                RemoveContainerOptionsBuilder::new()
+                    .v(true)
+                    .force(true)
+                    .build(),
+            ),
+        )
+        .await
+        .ok();
+}
+
+impl LocalDeployment {
+    pub async fn stop(&self, ctx: &DeployableContext) {
+        for container in &self.containers {
+            remove_container(ctx, container).await;
+        }
+        ctx.docker.remove_network(&self.network_name).await.ok();
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     env,
     fs::{self, File},
     io::Write,
@@ -8,20 +8,35 @@ use std::{
 };
 
 use bollard::auth::DockerCredentials;
+use chrono::NaiveDateTime;
 use clap::{Parser, Subcommand, command};
-use deployer_common::challenge::{
-    Challenge, Container, DeployableChallenge, DeployableContext, DeploymentStrategy, ExposeType,
-    Flag, PointRange, is_valid_id,
+use deployer_common::{
+    challenge::{
+        Challenge, Container, DeployableChallenge, DeployableContext, DeploymentStrategy,
+        ExposeType, Flag, PointRange, is_valid_id,
+    },
+    storage::{ApiStorage, Storage},
 };
-use dialoguer::{Select, theme::SimpleTheme};
+use dialoguer::{Confirm, Select, theme::SimpleTheme};
 use eyre::{Result, eyre};
-use google_cloud_storage::client::{Client as GcsClient, ClientConfig};
 use reqwest::{Client, Url, cookie::Jar};
 use rustyline::DefaultEditor;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tokio::{sync::Semaphore, task::JoinSet};
 use walkdir::WalkDir;
 
+use crate::{
+    diff::{ChallengeFields, PlatformChallenge, diff_fields},
+    lint::{Linter, OutputFormat, Severity, print_issues},
+    tags::{TagExpr, build_selected},
+};
+
+mod diff;
+mod lint;
+mod local;
+mod tags;
+
 #[derive(Debug, Parser)]
 #[command(name = "nerine")]
 #[command(about = "Tool for managing challenges with nerine", long_about = None)]
@@ -48,9 +63,59 @@ enum Commands {
         #[arg(short, long)]
         all: bool,
 
+        /// Only builds challenges whose tags match, e.g. `web & !hard` or `(pwn | rev) & easy`.
+        /// Build groups count as tags, naming one also builds the challenges in it
+        #[arg(short, long)]
+        tags: Option<TagExpr>,
+
         /// Exits without building if there are any toml parse errors
         #[arg(short, long)]
         strict: bool,
+
+        /// Rebuilds containers even if their build context is unchanged
+        #[arg(short, long)]
+        force: bool,
+
+        /// Number of containers to build and push concurrently
+        #[arg(short, long, default_value_t = 1)]
+        jobs: usize,
+    },
+
+    /// Builds and starts a challenge on the local docker daemon until interrupted
+    Run {
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Uses the existing local images instead of building them
+        #[arg(long)]
+        no_build: bool,
+    },
+
+    /// Starts a challenge locally and checks that its solver prints the flag
+    Test {
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Uses the existing local images instead of building them
+        #[arg(long)]
+        no_build: bool,
+    },
+
+    /// Checks challenge.toml files for mistakes
+    Lint {
+        #[arg()]
+        paths: Vec<PathBuf>,
+
+        /// Deployer HOST_KEYCHAINS file, to check that hosts exist and can serve http
+        #[arg(short, long, env = "HOST_KEYCHAINS")]
+        keychain: Option<PathBuf>,
+
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+
+        /// Also fails on warnings
+        #[arg(short, long)]
+        strict: bool,
     },
 
     Platform {
@@ -67,11 +132,54 @@ enum PlatformCommands {
         /// Specifies which build group to use
         #[arg(short = 'g', long)]
         build_group: Option<String>,
-        /// Skip pushing attachments to gcs and make all attachments empty in db
+        /// Skip pushing attachments, challenges on the platform keep the ones they have and new
+        /// challenges get none
         #[arg(short = 'n', long)]
         null_attachments: bool,
+        /// Hides or deletes platform challenges that aren't in the challenge repo, and destroys
+        /// their deployments. Has to run from the repo root without paths, and needs --yes
+        #[arg(long)]
+        prune: bool,
+        /// Only prints what would change on the platform
+        #[arg(long)]
+        dry_run: bool,
+        /// Applies changes without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+        /// Also pushes the attachments of unchanged challenges, which re-signs their links
+        #[arg(long)]
+        refresh_attachments: bool,
+    },
+    /// Downloads an archive of the whole event, for backups or post-event archives
+    Export {
+        #[arg(default_value = "archive.json")]
+        output: PathBuf,
+        /// Replaces team and user names and emails, and drops the text of wrong submissions
+        #[arg(long)]
+        anonymize: bool,
+    },
+    /// Restores an archive into a platform whose database has no teams, users or challenges
+    Import {
+        archive: PathBuf,
+        /// Also writes the archive's event.toml here, the platform has to run with it
+        #[arg(long)]
+        event_out: Option<PathBuf>,
     },
 }
+
+/// `PLATFORM_BASE` and a client authenticated with `PLATFORM_ADMIN_TOKEN`.
+fn platform_client() -> Result<(String, Client)> {
+    let platform_base = env::var("PLATFORM_BASE")?;
+    let jar = Jar::default();
+    jar.add_cookie_str(
+        &format!("admin_token={}", env::var("PLATFORM_ADMIN_TOKEN")?),
+        &Url::parse(&platform_base)?,
+    );
+    let client = reqwest::Client::builder()
+        .cookie_provider(Arc::new(jar))
+        .build()?;
+    Ok((platform_base, client))
+}
 // todo case sensitive or not?
 fn search_for(dir: &Path, filenames: &[&str]) -> Option<PathBuf> {
     for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
@@ -83,41 +191,193 @@ fn search_for(dir: &Path, filenames: &[&str]) -> Option<PathBuf> {
     None
 }
 
-fn get_all_challs(paths: &Vec<PathBuf>) -> impl Iterator<Item = DeployableChallenge> {
-    let chall_paths: Vec<PathBuf> = if paths.len() == 0 {
-        WalkDir::new(".")
+/// Picks the attachment storage from `ATTACHMENTS_STORAGE` (gcs, s3 or api). Private gcs and s3
+/// links go through the platform, which has to be configured with the same storage to re-sign them.
+async fn storage_from_env(client: &Client, platform_base: &str) -> Result<Storage> {
+    let kind = env::var("ATTACHMENTS_STORAGE").unwrap_or_else(|_| "gcs".to_owned());
+    if kind == "api" {
+        return Ok(Storage::Api(ApiStorage {
+            client: client.clone(),
+            platform_base: platform_base.to_owned(),
+        }));
+    }
+
+    let mut storage = Storage::remote_from_env(&kind)
+        .await?
+        .ok_or_else(|| eyre!("Unknown ATTACHMENTS_STORAGE {kind}, expected gcs, s3 or api"))?;
+    storage.redirect_through(platform_base);
+    Ok(storage)
+}
+
+/// Errors unless every challenge in the repo is being looked at, anything missing would be pruned.
+fn check_prune_scope(paths: &[PathBuf]) -> Result<()> {
+    if !paths.is_empty() {
+        return Err(eyre!(
+            "--prune would remove every challenge outside the given paths, run it from the challenge repo root without paths"
+        ));
+    }
+
+    // outside of git there's no telling where the repo root is
+    let Ok(output) = std::process::Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+    else {
+        return Ok(());
+    };
+    if output.status.success() {
+        let top = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()).canonicalize()?;
+        if top != env::current_dir()?.canonicalize()? {
+            return Err(eyre!(
+                "--prune would remove every challenge outside the current dir, run it from {}",
+                top.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Context for running challenges on the local daemon, images are never pushed.
+fn local_ctx() -> Result<DeployableContext> {
+    Ok(DeployableContext {
+        docker: bollard::Docker::connect_with_local_defaults()?,
+        docker_credentials: None,
+        image_prefix: "".to_string(),
+        repo: env::var("DOCKER_REPO").unwrap_or_else(|_| "nerine-local".to_owned()),
+    })
+}
+
+/// Dirs of every challenge under the current dir if `paths` is empty, otherwise just `paths`.
+fn chall_roots(paths: &Vec<PathBuf>) -> Vec<PathBuf> {
+    if paths.len() == 0 {
+        let mut roots: Vec<PathBuf> = WalkDir::new(".")
             .into_iter()
             .filter_map(|e| e.ok())
             .filter(|e| e.file_name() == "challenge.toml")
             .map(|e| e.path().parent().unwrap().to_owned())
-            .collect()
+            .collect();
+        roots.sort();
+        roots
     } else {
         paths.clone()
-    };
+    }
+}
 
-    let challs: Vec<Result<DeployableChallenge>> = chall_paths
-        .into_iter()
-        .map(|p| {
-            DeployableChallenge::from_root(p.clone()).map_err(|err| {
-                eyre!(
-                    "at {}:\n{}",
-                    p.join("challenge.toml").to_str().unwrap().to_string(),
-                    err.to_string()
-                )
-            })
-        })
-        .collect();
+/// Every challenge under `paths`, and the errors of the ones whose challenge.toml didn't parse.
+fn parse_all_challs(paths: &Vec<PathBuf>) -> (Vec<DeployableChallenge>, Vec<eyre::Report>) {
+    let chall_paths = chall_roots(paths);
 
-    let parse_errors: Vec<&eyre::ErrReport> =
-        challs.iter().filter_map(|c| c.as_ref().err()).collect();
-    if parse_errors.len() > 0 {
+    let mut challs = vec![];
+    let mut parse_errors = vec![];
+    for p in chall_paths {
+        match DeployableChallenge::from_root(p.clone()) {
+            Ok(c) => challs.push(c),
+            Err(err) => parse_errors.push(eyre!(
+                "at {}:\n{}",
+                p.join("challenge.toml").to_str().unwrap().to_string(),
+                err.to_string()
+            )),
+        }
+    }
+
+    (challs, parse_errors)
+}
+
+fn print_parse_errors(parse_errors: &[eyre::Report]) {
+    if !parse_errors.is_empty() {
         eprintln!("Toml errors:");
         for err in parse_errors {
             eprintln!("{}", err)
         }
     }
+}
 
-    return challs.into_iter().filter_map(|c| c.ok());
+fn get_all_challs(paths: &Vec<PathBuf>) -> impl Iterator<Item = DeployableChallenge> {
+    let (challs, parse_errors) = parse_all_challs(paths);
+    print_parse_errors(&parse_errors);
+
+    challs.into_iter()
+}
+
+enum BuildStatus {
+    Built,
+    UpToDate,
+    Failed(eyre::Report),
+}
+
+// returns whether anything was built
+async fn build_and_push(
+    ctx: &DeployableContext,
+    chall: &DeployableChallenge,
+    ct: &str,
+    force: bool,
+) -> Result<bool> {
+    let prefix = format!("[{}/{}]", chall.chall.id, ct);
+    if !force && !chall.is_changed(ctx, ct).await? {
+        println!("{prefix} up to date, skipping");
+        return Ok(false);
+    }
+
+    println!("{prefix} building");
+    chall.build_ct(ctx, ct).await?;
+    println!("{prefix} pushing");
+    chall.chall.push_ct(ctx, ct).await?;
+    println!("{prefix} done");
+    Ok(true)
+}
+
+// solvers are cheap to rebuild from cache, so they are always built
+async fn build_and_push_solve(
+    ctx: &DeployableContext,
+    chall: &DeployableChallenge,
+) -> Result<bool> {
+    let prefix = format!("[{}/solve]", chall.chall.id);
+    println!("{prefix} building");
+    chall.build_solve(ctx).await?;
+    println!("{prefix} pushing");
+    chall.chall.push_solve(ctx).await?;
+    println!("{prefix} done");
+    Ok(true)
+}
+
+fn print_build_summary(results: &[(String, String, BuildStatus)]) {
+    let rows: Vec<[String; 3]> = results
+        .iter()
+        .map(|(id, ct, status)| {
+            let status = match status {
+                BuildStatus::Built => "built".to_owned(),
+                BuildStatus::UpToDate => "up to date".to_owned(),
+                BuildStatus::Failed(e) => format!("failed: {e}"),
+            };
+            [id.clone(), ct.clone(), status]
+        })
+        .collect();
+
+    let header = [
+        "challenge".to_owned(),
+        "container".to_owned(),
+        "status".to_owned(),
+    ];
+    let widths: Vec<usize> = (0..2)
+        .map(|i| {
+            rows.iter()
+                .chain([&header])
+                .map(|r| r[i].len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    println!();
+    for row in [&header].into_iter().chain(&rows) {
+        println!(
+            "{:w0$}  {:w1$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            w0 = widths[0],
+            w1 = widths[1]
+        );
+    }
 }
 
 #[tokio::main]
@@ -226,6 +486,9 @@ async fn main() -> Result<()> {
                 }),
                 cap_add: None,
                 privileged: None,
+                build_args: None,
+                target: None,
+                platform: None,
             };
 
             let chall = Challenge {
@@ -236,13 +499,20 @@ async fn main() -> Result<()> {
                 visible: None,
                 group: None,
                 build_group: None,
+                tags: None,
+                difficulty: None,
+                release_at: None,
                 category: path
                     .parent()
                     .and_then(|p| p.file_name())
                     .and_then(|f| f.to_str())
                     .unwrap_or("unknown")
                     .to_string(),
-                points: PointRange { min: 100, max: 500 },
+                points: PointRange {
+                    min: 100,
+                    max: 500,
+                    decay: None,
+                },
                 description: "challenge description".to_string(),
                 container: Some({
                     // FIXME
@@ -254,6 +524,7 @@ async fn main() -> Result<()> {
                 bump_seed: 0,
                 provide: None,
                 host: None,
+                solve: None,
             };
 
             path.push("challenge.toml");
@@ -266,11 +537,14 @@ async fn main() -> Result<()> {
             paths,
             build_group,
             all,
+            tags,
             strict,
+            force,
+            jobs,
         } => {
             let valid_challs: Vec<DeployableChallenge> = get_all_challs(&paths)
                 .filter(|c| c.chall.container.is_some())
-                .filter(|c| all || c.chall.build_group == build_group)
+                .filter(|c| build_selected(&c.chall, build_group.as_deref(), all, tags.as_ref()))
                 .collect();
             println!("Building following challenges:");
             for chall in &valid_challs {
@@ -292,17 +566,114 @@ async fn main() -> Result<()> {
                 repo: env::var("DOCKER_REPO")?,
             };
 
+            let ctx = Arc::new(ctx);
+            let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+            let mut tasks = JoinSet::new();
             for chall in valid_challs {
-                println!("building chall {}", chall.chall.id);
-                // TODO i suspect this might not be working
-                chall.pull(&ctx).await?;
-                match chall.build(&ctx).await {
-                    Ok(_) => {
-                        println!("pushing chall {}", chall.chall.id);
-                        chall.push(&ctx).await?;
-                    }
-                    Err(e) => eprintln!("failed to build {}: {e:?}", chall.chall.id),
-                };
+                let chall = Arc::new(chall);
+                let cts = chall.chall.container.iter().flat_map(|c| c.keys());
+                // (name, is the solver)
+                let jobs = cts.map(|ct| (ct.clone(), false)).chain(
+                    chall
+                        .chall
+                        .solve
+                        .as_ref()
+                        .map(|_| ("solve".to_owned(), true)),
+                );
+                for (ct, is_solve) in jobs {
+                    let (ctx, chall) = (ctx.clone(), chall.clone());
+                    let semaphore = semaphore.clone();
+                    tasks.spawn(async move {
+                        let _permit = semaphore.acquire_owned().await.unwrap();
+                        let res = if is_solve {
+                            build_and_push_solve(&ctx, &chall).await
+                        } else {
+                            build_and_push(&ctx, &chall, &ct, force).await
+                        };
+                        let status = match res {
+                            Ok(true) => BuildStatus::Built,
+                            Ok(false) => BuildStatus::UpToDate,
+                            Err(e) => {
+                                eprintln!("[{}/{}] failed: {e:?}", chall.chall.id, ct);
+                                BuildStatus::Failed(e)
+                            }
+                        };
+                        (chall.chall.id.clone(), ct, status)
+                    });
+                }
+            }
+
+            let mut results = tasks.join_all().await;
+            results.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+            print_build_summary(&results);
+
+            let failed = results
+                .iter()
+                .filter(|(_, _, s)| matches!(s, BuildStatus::Failed(_)))
+                .count();
+            if failed > 0 {
+                return Err(eyre!("{failed} container(s) failed to build"));
+            }
+        }
+        Commands::Run { path, no_build } => {
+            let ctx = local_ctx()?;
+            let dc = DeployableChallenge::from_root(path)?;
+            if !no_build {
+                dc.build(&ctx).await?;
+            }
+
+            let deployment = local::start(&ctx, &dc).await?;
+            println!("Running {}, press ctrl-c to stop", dc.chall.id);
+            tokio::signal::ctrl_c().await?;
+            deployment.stop(&ctx).await;
+        }
+        Commands::Test { path, no_build } => {
+            let ctx = local_ctx()?;
+            let dc = DeployableChallenge::from_root(path)?;
+            if dc.chall.solve.is_none() {
+                return Err(eyre!("{} has no solve configured", dc.chall.id));
+            }
+            if !no_build {
+                dc.build(&ctx).await?;
+                dc.build_solve(&ctx).await?;
+            }
+
+            let deployment = local::start(&ctx, &dc).await?;
+            let res = dc
+                .chall
+                .run_solve(&ctx, &deployment.network_name, &dc.flag()?)
+                .await;
+            deployment.stop(&ctx).await;
+
+            let res = res?.ok_or_else(|| eyre!("{} has no solve configured", dc.chall.id))?;
+            println!("{}", res.output.trim_end());
+            match res.exit_code {
+                Some(code) => println!("solver exited with code {code}"),
+                None => println!("solver timed out"),
+            }
+            if !res.passed {
+                return Err(eyre!(
+                    "{} failed, solver did not print the flag",
+                    dc.chall.id
+                ));
+            }
+            println!("{} passed", dc.chall.id);
+        }
+        Commands::Lint {
+            paths,
+            keychain,
+            format,
+            strict,
+        } => {
+            let issues = Linter::new(keychain.as_deref())?.lint(&chall_roots(&paths));
+            print_issues(&issues, format)?;
+
+            let failing = issues
+                .iter()
+                .filter(|i| strict || i.severity == Severity::Error)
+                .count();
+            if failing > 0 {
+                return Err(eyre!("{failing} lint issue(s)"));
             }
         }
         Commands::Platform { command } => match command {
@@ -310,6 +681,10 @@ async fn main() -> Result<()> {
                 paths,
                 build_group,
                 null_attachments,
+                prune,
+                dry_run,
+                yes,
+                refresh_attachments,
             } => {
                 #[derive(Deserialize, Serialize)]
                 pub struct Category {
@@ -325,24 +700,33 @@ async fn main() -> Result<()> {
                     pub description: String,
                     pub points_min: i32,
                     pub points_max: i32,
+                    pub points_decay: Option<f64>,
                     pub flag: String,
                     pub attachments: serde_json::Value,
                     pub strategy: DeploymentStrategy,
                     pub visible: bool,
+                    pub difficulty: Option<String>,
+                    pub tags: Vec<String>,
+                    pub release_at: Option<NaiveDateTime>,
 
                     pub category_id: i32,
                     pub group_id: Option<i32>,
                 }
 
-                let platform_base = env::var("PLATFORM_BASE")?;
-                let jar = Jar::default();
-                jar.add_cookie_str(
-                    &format!("admin_token={}", env::var("PLATFORM_ADMIN_TOKEN")?),
-                    &Url::parse(&platform_base)?,
-                );
-                let client = reqwest::Client::builder()
-                    .cookie_provider(Arc::new(jar))
-                    .build()?;
+                if prune {
+                    check_prune_scope(&paths)?;
+                }
+                let (challs, parse_errors) = parse_all_challs(&paths);
+                print_parse_errors(&parse_errors);
+                // a challenge that doesn't parse would look removed and get pruned
+                if prune && !parse_errors.is_empty() {
+                    return Err(eyre!(
+                        "{} challenge.toml(s) failed to parse, fix them before pruning",
+                        parse_errors.len()
+                    ));
+                }
+
+                let (platform_base, client) = platform_client()?;
                 let mut categories: HashMap<String, i32> = client
                     .get(format!("{platform_base}/api/admin/challs/category"))
                     .send()
@@ -353,25 +737,154 @@ async fn main() -> Result<()> {
                     .into_iter()
                     .map(|c| (c.name, c.id))
                     .collect();
+                // groups have the same shape as categories
+                let mut groups: HashMap<String, i32> = client
+                    .get(format!("{platform_base}/api/admin/challs/group"))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<Vec<Category>>()
+                    .await?
+                    .into_iter()
+                    .map(|c| (c.name, c.id))
+                    .collect();
+                let platform_challs: Vec<PlatformChallenge> = client
+                    .get(format!("{platform_base}/api/admin/challs"))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                // pushed back as is with --null-attachments
+                let current_attachments: HashMap<String, Value> = platform_challs
+                    .iter()
+                    .map(|c| (c.public_id.clone(), c.attachments.clone()))
+                    .collect();
+                let current: HashMap<String, ChallengeFields> = platform_challs
+                    .into_iter()
+                    .map(|c| (c.public_id.clone(), c.into()))
+                    .collect();
+
+                // only used for generated attachments, doesn't connect until then
+                let docker = bollard::Docker::connect_with_local_defaults()?;
+
+                let mut pending = vec![];
+                for dc in challs.iter().filter(|c| c.chall.build_group == build_group) {
+                    let chall = &dc.chall;
+                    // generated attachments are expensive to build, so these are pushed as is
+                    let contents = if null_attachments {
+                        vec![]
+                    } else {
+                        dc.read_attachments(&docker).await?
+                    };
+                    let fields = ChallengeFields {
+                        name: chall.name.clone(),
+                        author: chall.author.clone(),
+                        description: chall.description.clone(),
+                        points_min: chall.points.min,
+                        points_max: chall.points.max,
+                        points_decay: chall.points.decay,
+                        flag: dc.flag()?,
+                        strategy: chall.strategy,
+                        visible: chall.visible != Some(false),
+                        category: chall.category.clone(),
+                        group: chall.group.clone(),
+                        difficulty: chall.difficulty.clone(),
+                        release_at: chall.release_at,
+                        tags: {
+                            let mut tags = chall.tags.clone().unwrap_or_default();
+                            tags.sort();
+                            tags.dedup();
+                            tags
+                        },
+                        attachments: if null_attachments {
+                            current
+                                .get(&chall.id)
+                                .map(|c| c.attachments.clone())
+                                .unwrap_or_default()
+                        } else {
+                            dc.attachment_digests(&contents)?
+                        },
+                    };
+                    match current.get(&chall.id) {
+                        None => {
+                            println!("+ {} (new in {})", chall.id, chall.category);
+                            pending.push((dc, contents));
+                        }
+                        Some(old) => {
+                            let changes = diff_fields(old, &fields);
+                            if !changes.is_empty() {
+                                println!("~ {}", chall.id);
+                                for change in changes {
+                                    println!("    {change}");
+                                }
+                                pending.push((dc, contents));
+                            } else if refresh_attachments && contents.iter().any(Option::is_some) {
+                                println!("~ {}", chall.id);
+                                println!("    attachments: refreshed");
+                                pending.push((dc, contents));
+                            }
+                        }
+                    }
+                }
 
-                let gcs_client = if null_attachments {
+                let local_ids: HashSet<&str> = challs.iter().map(|c| c.chall.id.as_str()).collect();
+                let mut missing: Vec<_> = current
+                    .keys()
+                    .filter(|id| !local_ids.contains(id.as_str()))
+                    .collect();
+                missing.sort();
+                for id in &missing {
+                    if prune {
+                        println!("- {id} (deleted, or hidden if it has solves)");
+                    } else {
+                        println!("- {id} (not in the given paths, left as is)");
+                    }
+                }
+                if !prune {
+                    missing.clear();
+                }
+                if pending.is_empty() && missing.is_empty() {
+                    println!("no challenges to update");
+                }
+
+                if dry_run {
+                    return Ok(());
+                }
+                // deleting challenges is too destructive for a prompt that's easy to skim past
+                if !missing.is_empty() && !yes {
+                    return Err(eyre!(
+                        "--prune would remove {} challenge(s), pass --yes to confirm",
+                        missing.len()
+                    ));
+                }
+                let changes = pending.len() + missing.len();
+                if changes > 0
+                    && !yes
+                    && !Confirm::with_theme(&SimpleTheme)
+                        .with_prompt(format!("Update {changes} challenge(s)?"))
+                        .default(false)
+                        .interact()?
+                {
+                    println!("aborted");
+                    return Ok(());
+                }
+                let storage = if null_attachments {
                     None
                 } else {
-                    Some(GcsClient::new(
-                        ClientConfig::default().with_auth().await.unwrap(),
-                    ))
+                    Some(storage_from_env(&client, &platform_base).await?)
                 };
-                for ref dc in get_all_challs(&paths).filter(|c| c.chall.build_group == build_group)
-                {
-                    let DeployableChallenge { chall, root } = dc;
-                    let attachments = if null_attachments {
-                        HashMap::new()
-                    } else {
-                        dc.push_attachments(
-                            gcs_client.as_ref().unwrap(),
-                            env::var("GCS_ATTACHMENTS_BUCKET")?,
-                        )
-                        .await?
+                for (dc, contents) in pending {
+                    let DeployableChallenge { chall, .. } = dc;
+                    let attachments = match &storage {
+                        None => current_attachments
+                            .get(&chall.id)
+                            .cloned()
+                            .unwrap_or_else(|| Value::Object(Default::default())),
+                        Some(storage) => dc
+                            .push_attachments(contents, storage)
+                            .await?
+                            .serialize(serde_json::value::Serializer)?,
                     };
                     client
                         .patch(format!("{platform_base}/api/admin/challs"))
@@ -382,15 +895,14 @@ async fn main() -> Result<()> {
                             description: chall.description.clone(),
                             points_max: chall.points.max,
                             points_min: chall.points.min,
-                            flag: match chall.flag.clone() {
-                                Flag::Raw(flag) => flag,
-                                Flag::File { file } => {
-                                    fs::read_to_string(root.join(file))?.trim().to_string()
-                                }
-                            },
-                            attachments: attachments.serialize(serde_json::value::Serializer)?,
+                            points_decay: chall.points.decay,
+                            flag: dc.flag()?,
+                            attachments,
                             strategy: chall.strategy,
                             visible: chall.visible != Some(false),
+                            difficulty: chall.difficulty.clone(),
+                            tags: chall.tags.clone().unwrap_or_default(),
+                            release_at: chall.release_at,
                             category_id: match categories.get(&chall.category) {
                                 Some(c) => *c,
                                 None => {
@@ -414,7 +926,33 @@ async fn main() -> Result<()> {
                                     new_category.id
                                 }
                             },
-                            group_id: None,
+                            group_id: match &chall.group {
+                                None => None,
+                                Some(group) => Some(match groups.get(group) {
+                                    Some(g) => *g,
+                                    None => {
+                                        #[derive(Serialize)]
+                                        struct CreateGroup {
+                                            name: String,
+                                        }
+
+                                        let new_group: Category = client
+                                            .post(format!("{platform_base}/api/admin/challs/group"))
+                                            .json(&CreateGroup {
+                                                name: group.clone(),
+                                            })
+                                            .send()
+                                            .await?
+                                            .error_for_status()?
+                                            .json()
+                                            .await?;
+
+                                        groups.insert(new_group.name, new_group.id);
+
+                                        new_group.id
+                                    }
+                                }),
+                            },
                         })
                         .send()
                         .await?
@@ -423,17 +961,85 @@ async fn main() -> Result<()> {
                     println!("updated {}", chall.id);
                 }
 
-                let challs_json: HashMap<String, Challenge> = get_all_challs(&paths)
+                let challs_json: HashMap<String, Challenge> = challs
+                    .into_iter()
                     .map(|dc| (dc.chall.id.clone(), dc.chall))
                     .collect();
 
                 client
                     .post(format!("{platform_base}/api/admin/challs/load_deployer"))
+                    .query(&[("prune", prune)])
                     .json(&challs_json)
                     .send()
                     .await?
                     .error_for_status()?;
-                println!("reloaded deployer")
+                println!("reloaded deployer");
+
+                // after the deployer load, which destroys their deployments first
+                for id in missing {
+                    #[derive(Serialize)]
+                    struct PruneChallenge<'a> {
+                        id: &'a str,
+                    }
+                    #[derive(Deserialize)]
+                    struct PruneResult {
+                        deleted: bool,
+                    }
+
+                    let res: PruneResult = client
+                        .post(format!("{platform_base}/api/admin/challs/prune"))
+                        .json(&PruneChallenge { id })
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .json()
+                        .await?;
+                    if res.deleted {
+                        println!("deleted {id}");
+                    } else {
+                        println!("hid {id}");
+                    }
+                }
+            }
+            PlatformCommands::Export { output, anonymize } => {
+                let (platform_base, client) = platform_client()?;
+                let archive = client
+                    .get(format!("{platform_base}/api/admin/archive"))
+                    .query(&[("anonymize", anonymize)])
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?;
+                fs::write(&output, archive)?;
+                println!("wrote {}", output.display());
+            }
+            PlatformCommands::Import { archive, event_out } => {
+                // only the event.toml is needed here, the platform checks the rest
+                #[derive(Deserialize)]
+                struct ArchiveEvent {
+                    event_toml: String,
+                }
+
+                let data = fs::read(&archive)?;
+                if let Some(event_out) = event_out {
+                    let event: ArchiveEvent = serde_json::from_slice(&data)?;
+                    fs::write(&event_out, event.event_toml)?;
+                    println!("wrote {}", event_out.display());
+                }
+
+                let (platform_base, client) = platform_client()?;
+                let res = client
+                    .post(format!("{platform_base}/api/admin/archive"))
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(data)
+                    .send()
+                    .await?;
+                if !res.status().is_success() {
+                    return Err(eyre!("import failed: {}", res.text().await?));
+                }
+                let counts: Value = res.json().await?;
+                println!("imported {counts}");
             }
         },
     }
@@ -0,0 +1,250 @@
+use std::{iter::Peekable, str::FromStr, vec::IntoIter};
+
+use deployer_common::challenge::Challenge;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Tag(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '&' => Token::And,
+            '|' => Token::Or,
+            '!' => Token::Not,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            c if c.is_whitespace() => continue,
+            c if is_tag_char(c) => {
+                let mut tag = c.to_string();
+                while let Some(&c) = chars.peek().filter(|c| is_tag_char(**c)) {
+                    tag.push(c);
+                    chars.next();
+                }
+                Token::Tag(tag)
+            }
+            c => return Err(format!("unexpected character {c:?} in tag expression")),
+        });
+    }
+    Ok(tokens)
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':' | '/')
+}
+
+/// Boolean expression over challenge tags, like `web & !hard` or `(pwn | rev) & easy`.
+/// `!` binds tighter than `&`, which binds tighter than `|`.
+#[derive(Debug, Clone)]
+pub enum TagExpr {
+    Tag(String),
+    Not(Box<TagExpr>),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+}
+
+impl TagExpr {
+    pub fn matches(&self, tags: &[String]) -> bool {
+        match self {
+            Self::Tag(tag) => tags.contains(tag),
+            Self::Not(e) => !e.matches(tags),
+            Self::And(a, b) => a.matches(tags) && b.matches(tags),
+            Self::Or(a, b) => a.matches(tags) || b.matches(tags),
+        }
+    }
+
+    /// Whether `tag` appears anywhere in the expression, negated or not.
+    pub fn mentions(&self, tag: &str) -> bool {
+        match self {
+            Self::Tag(t) => t == tag,
+            Self::Not(e) => e.mentions(tag),
+            Self::And(a, b) | Self::Or(a, b) => a.mentions(tag) || b.mentions(tag),
+        }
+    }
+}
+
+/// Whether `nerine build` should build `chall`. A challenge's build group counts as one of its
+/// tags, and challenges in a build group are left out unless the group is picked with `-g`, named
+/// in the tag expression, or `--all` is given.
+pub fn build_selected(
+    chall: &Challenge,
+    build_group: Option<&str>,
+    all: bool,
+    tags: Option<&TagExpr>,
+) -> bool {
+    let in_group = match chall.build_group.as_deref() {
+        _ if all => true,
+        Some(group) => build_group == Some(group) || tags.is_some_and(|t| t.mentions(group)),
+        None => build_group.is_none(),
+    };
+
+    let mut chall_tags = chall.tags.clone().unwrap_or_default();
+    chall_tags.extend(chall.build_group.clone());
+    in_group && tags.is_none_or(|t| t.matches(&chall_tags))
+}
+
+struct Parser {
+    tokens: Peekable<IntoIter<Token>>,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<TagExpr, String> {
+        let mut expr = self.and()?;
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            expr = TagExpr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<TagExpr, String> {
+        let mut expr = self.not()?;
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            expr = TagExpr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<TagExpr, String> {
+        match self.tokens.next() {
+            Some(Token::Not) => Ok(TagExpr::Not(Box::new(self.not()?))),
+            Some(Token::Tag(tag)) => Ok(TagExpr::Tag(tag)),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("unclosed ( in tag expression".to_owned()),
+                }
+            }
+            Some(t) => Err(format!("unexpected {t:?} in tag expression")),
+            None => Err("tag expression ended early".to_owned()),
+        }
+    }
+}
+
+impl FromStr for TagExpr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?.into_iter().peekable(),
+        };
+        let expr = parser.or()?;
+        match parser.tokens.next() {
+            None => Ok(expr),
+            Some(t) => Err(format!("unexpected {t:?} in tag expression")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|t| t.to_string()).collect()
+    }
+
+    fn matches(expr: &str, t: &[&str]) -> bool {
+        expr.parse::<TagExpr>().unwrap().matches(&tags(t))
+    }
+
+    #[test]
+    fn precedence() {
+        // a | (b & !c)
+        assert!(matches("a | b & !c", &["a", "c"]));
+        assert!(matches("a | b & !c", &["b"]));
+        assert!(!matches("a | b & !c", &["b", "c"]));
+        // (!a) & b
+        assert!(matches("!a & b", &["b"]));
+        assert!(!matches("!a & b", &["a", "b"]));
+        assert!(matches("!!a", &["a"]));
+    }
+
+    #[test]
+    fn parentheses() {
+        assert!(!matches("(a | b) & !c", &["a", "c"]));
+        assert!(matches("(a | b) & !c", &["b"]));
+        assert!(matches("!(a & b)", &["a"]));
+        assert!(!matches("!(a & b)", &["a", "b"]));
+        assert!(matches("((web:easy))", &["web:easy"]));
+    }
+
+    #[test]
+    fn errors() {
+        for expr in [
+            "", "a &", "& a", "(a | b", "a | b)", "a b", "a $ b", "!", "()",
+        ] {
+            assert!(expr.parse::<TagExpr>().is_err(), "{expr:?} parsed");
+        }
+    }
+
+    #[test]
+    fn mentions() {
+        let expr: TagExpr = "web & !(heavy | slow)".parse().unwrap();
+        assert!(expr.mentions("heavy"));
+        assert!(expr.mentions("web"));
+        assert!(!expr.mentions("pwn"));
+    }
+
+    fn chall(t: &[&str], build_group: Option<&str>) -> Challenge {
+        let mut chall: Challenge = toml::from_str(
+            r#"
+            id = "chall"
+            name = "chall"
+            author = "author"
+            description = ""
+            flag = "flag{test}"
+            category = "misc"
+            points = { min = 100, max = 500 }
+            "#,
+        )
+        .unwrap();
+        chall.tags = Some(tags(t));
+        chall.build_group = build_group.map(str::to_owned);
+        chall
+    }
+
+    fn selected(
+        chall: &Challenge,
+        build_group: Option<&str>,
+        all: bool,
+        expr: Option<&str>,
+    ) -> bool {
+        let expr = expr.map(|e| e.parse::<TagExpr>().unwrap());
+        build_selected(chall, build_group, all, expr.as_ref())
+    }
+
+    #[test]
+    fn build_selection() {
+        let web = chall(&["web", "easy"], None);
+        assert!(selected(&web, None, false, None));
+        assert!(selected(&web, None, false, Some("web & !hard")));
+        assert!(!selected(&web, None, false, Some("pwn")));
+        // picking a build group leaves out everything outside it
+        assert!(!selected(&web, Some("heavy"), false, None));
+    }
+
+    #[test]
+    fn build_groups() {
+        let heavy = chall(&["pwn"], Some("heavy"));
+        // left out by default
+        assert!(!selected(&heavy, None, false, None));
+        assert!(!selected(&heavy, None, false, Some("pwn")));
+        // unless picked with -g, named in the expression or with --all
+        assert!(selected(&heavy, Some("heavy"), false, None));
+        assert!(selected(&heavy, None, false, Some("heavy & pwn")));
+        assert!(selected(&heavy, None, true, None));
+        // the expression still has to match
+        assert!(!selected(&heavy, Some("heavy"), false, Some("web")));
+        assert!(!selected(&heavy, None, false, Some("!heavy")));
+        assert!(!selected(&heavy, Some("other"), false, None));
+    }
+}
@@ -0,0 +1,161 @@
+use eyre::{Context, Result};
+use flate2::{Compression, GzBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, Cursor, Write},
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+use zip::{DateTime, ZipWriter, write::SimpleFileOptions};
+
+use crate::{build_context::is_executable, ignore::IgnoreRules};
+
+// 1980-01-01, the earliest time zip can represent
+const ARCHIVE_MTIME: u64 = 315532800;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    #[serde(rename = "zip")]
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::TarGz => "tar.gz",
+            Self::Zip => "zip",
+        }
+    }
+}
+
+struct ArchiveEntry {
+    path: PathBuf,
+    // relative to the archived dir
+    rel: PathBuf,
+    dir: bool,
+    executable: bool,
+    size: u64,
+}
+
+impl ArchiveEntry {
+    fn mode(&self) -> u32 {
+        if self.dir || self.executable {
+            0o755
+        } else {
+            0o644
+        }
+    }
+}
+
+fn collect_entries(dir: &Path, exclude: &IgnoreRules) -> Result<Vec<ArchiveEntry>> {
+    let prune = exclude.can_prune();
+    let mut entries = vec![];
+    let walker = WalkDir::new(dir)
+        .min_depth(1)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            !prune
+                || !e.file_type().is_dir()
+                || !exclude.is_ignored(e.path().strip_prefix(dir).unwrap(), true)
+        });
+    for entry in walker {
+        let entry = entry.with_context(|| format!("Failed to read {}", dir.display()))?;
+        let rel = entry.path().strip_prefix(dir)?.to_owned();
+        if exclude.is_ignored(&rel, entry.file_type().is_dir()) {
+            continue;
+        }
+
+        let meta = entry.metadata()?;
+        entries.push(ArchiveEntry {
+            path: entry.path().to_owned(),
+            rel,
+            dir: meta.is_dir(),
+            executable: is_executable(&meta),
+            size: meta.len(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Archives `dir` with every entry under `prefix/`. Entries are sorted and have fixed
+/// timestamps, owners and permissions, so the same contents always give the same bytes.
+pub fn create_archive(
+    dir: &Path,
+    prefix: &str,
+    exclude: &IgnoreRules,
+    format: ArchiveFormat,
+) -> Result<Vec<u8>> {
+    let entries = collect_entries(dir, exclude)?;
+    let name_of = |rel: &Path| format!("{prefix}/{}", rel.to_string_lossy());
+
+    match format {
+        ArchiveFormat::TarGz => {
+            let enc = GzBuilder::new()
+                .mtime(0)
+                .write(Vec::new(), Compression::default());
+            let mut tar_ = tar::Builder::new(enc);
+
+            let mut append = |name: String, mode: u32, size: u64, data: &mut dyn io::Read| {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(if name.ends_with('/') {
+                    tar::EntryType::Directory
+                } else {
+                    tar::EntryType::Regular
+                });
+                header.set_mode(mode);
+                header.set_size(size);
+                header.set_mtime(ARCHIVE_MTIME);
+                header.set_uid(0);
+                header.set_gid(0);
+                tar_.append_data(&mut header, name, data)
+            };
+
+            append(format!("{prefix}/"), 0o755, 0, &mut io::empty())?;
+            for entry in &entries {
+                if entry.dir {
+                    append(
+                        format!("{}/", name_of(&entry.rel)),
+                        0o755,
+                        0,
+                        &mut io::empty(),
+                    )?;
+                } else {
+                    append(
+                        name_of(&entry.rel),
+                        entry.mode(),
+                        entry.size,
+                        &mut File::open(&entry.path)?,
+                    )
+                    .with_context(|| format!("Failed to archive {}", entry.path.display()))?;
+                }
+            }
+
+            Ok(tar_.into_inner()?.finish()?)
+        }
+        ArchiveFormat::Zip => {
+            let mut zip_ = ZipWriter::new(Cursor::new(Vec::new()));
+            let options = SimpleFileOptions::default().last_modified_time(DateTime::default());
+
+            zip_.add_directory(format!("{prefix}/"), options.unix_permissions(0o755))?;
+            for entry in &entries {
+                let options = options.unix_permissions(entry.mode());
+                if entry.dir {
+                    zip_.add_directory(format!("{}/", name_of(&entry.rel)), options)?;
+                } else {
+                    zip_.start_file(name_of(&entry.rel), options)?;
+                    io::copy(&mut File::open(&entry.path)?, &mut zip_)
+                        .with_context(|| format!("Failed to archive {}", entry.path.display()))?;
+                }
+            }
+            zip_.flush()?;
+
+            Ok(zip_.finish()?.into_inner())
+        }
+    }
+}
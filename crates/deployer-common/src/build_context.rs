@@ -0,0 +1,135 @@
+use eyre::{Context, Result};
+use futures_util::Stream;
+use log::error;
+use sha2::Sha256;
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use tokio::sync::mpsc;
+use walkdir::WalkDir;
+
+use crate::ignore::IgnoreRules;
+
+/// Files of a docker build context after applying `.dockerignore`.
+#[derive(Debug, Clone)]
+pub struct BuildContext {
+    pub root: PathBuf,
+    // relative to root, sorted
+    pub entries: Vec<PathBuf>,
+}
+
+impl BuildContext {
+    pub fn new(root: PathBuf) -> Result<Self> {
+        let ignore = IgnoreRules::from_dockerignore_dir(&root)?;
+        let prune = ignore.can_prune();
+
+        let mut entries = vec![];
+        let walker = WalkDir::new(&root)
+            .min_depth(1)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|e| {
+                !prune
+                    || !e.file_type().is_dir()
+                    || !ignore.is_ignored(e.path().strip_prefix(&root).unwrap(), true)
+            });
+        for entry in walker {
+            let entry = entry
+                .with_context(|| format!("Failed to read Docker context {}", root.display()))?;
+            let rel = entry.path().strip_prefix(&root)?.to_owned();
+            // the daemon always needs these, even if they are ignored
+            let always = rel == Path::new("Dockerfile") || rel == Path::new(".dockerignore");
+            if always || !ignore.is_ignored(&rel, entry.file_type().is_dir()) {
+                entries.push(rel);
+            }
+        }
+
+        Ok(Self { root, entries })
+    }
+
+    /// Deterministic hash over the paths, types and contents of every entry.
+    pub fn hash_into(&self, hasher: &mut Sha256) -> Result<()> {
+        for rel in &self.entries {
+            let path = self.root.join(rel);
+            let meta = fs::symlink_metadata(&path)?;
+            hasher.write_all(rel.to_string_lossy().as_bytes())?;
+            hasher.write_all(&[0])?;
+            if meta.is_dir() {
+                hasher.write_all(b"d")?;
+            } else if meta.is_symlink() {
+                hasher.write_all(b"l")?;
+                hasher.write_all(fs::read_link(&path)?.to_string_lossy().as_bytes())?;
+            } else {
+                hasher.write_all(if is_executable(&meta) { b"x" } else { b"f" })?;
+                hasher.write_all(&meta.len().to_le_bytes())?;
+                io::copy(&mut fs::File::open(&path)?, hasher)?;
+            }
+            hasher.write_all(&[0])?;
+        }
+        Ok(())
+    }
+
+    pub fn append_to<W: Write>(&self, tar_: &mut tar::Builder<W>) -> Result<()> {
+        for rel in &self.entries {
+            let path = self.root.join(rel);
+            tar_.append_path_with_name(&path, rel)
+                .with_context(|| format!("Failed to add {} to Docker context", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Streams the context as an uncompressed tar, without buffering it to disk.
+    pub fn into_tar_stream(self) -> impl Stream<Item = io::Result<Vec<u8>>> + Send + 'static {
+        let (tx, rx) = mpsc::channel(16);
+        tokio::task::spawn_blocking(move || {
+            let mut tar_ = tar::Builder::new(BufWriter::with_capacity(
+                64 * 1024,
+                ChannelWriter(tx.clone()),
+            ));
+            tar_.sparse(false);
+            tar_.follow_symlinks(false);
+            let res = self
+                .append_to(&mut tar_)
+                .and_then(|_| Ok(tar_.into_inner()?.flush()?));
+            if let Err(e) = res {
+                error!(
+                    "failed to stream Docker context {}: {e:?}",
+                    self.root.display()
+                );
+                tx.blocking_send(Err(io::Error::other(e))).ok();
+            }
+        });
+
+        futures_util::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|chunk| (chunk, rx))
+        })
+    }
+}
+
+struct ChannelWriter(mpsc::Sender<io::Result<Vec<u8>>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Docker context was dropped"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn is_executable(meta: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_executable(_meta: &fs::Metadata) -> bool {
+    false
+}
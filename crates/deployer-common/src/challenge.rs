@@ -1,26 +1,30 @@
 use bollard::query_parameters::CreateImageOptionsBuilder;
+use chrono::NaiveDateTime;
 use eyre::{Context, Result, eyre};
-use flate2::{Compression, write::GzEncoder};
-use google_cloud_storage::{
-    client::Client,
-    http::objects::upload::{Media, UploadObjectRequest, UploadType},
-    sign::SignedURLOptions,
-};
-use log::info;
+use log::{debug, info};
 use serde_with::{DisplayFromStr, serde_as};
+use sha2::{Digest, Sha256};
 use std::{
     collections::HashMap,
-    fs::{self, File as StdFile},
+    fs,
     io::Read,
-    path::PathBuf,
-    time::Duration,
+    path::{Path, PathBuf},
 };
 
-use futures_util::StreamExt;
+use futures_util::{StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
-use tempdir::TempDir;
-use tokio::fs::File;
-use tokio_util::io::ReaderStream;
+
+use crate::{
+    archive::{ArchiveFormat, create_archive},
+    build_context::BuildContext,
+    ignore::IgnoreRules,
+    registry,
+    solve::Solve,
+    storage::AttachmentStorage,
+};
+
+/// Image label holding the hash of the build context and container config an image was built from.
+pub const CONTEXT_HASH_LABEL: &str = "gg.ctf.nerine.context-hash";
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Challenge {
@@ -30,10 +34,14 @@ pub struct Challenge {
     pub description: String,
     pub flag: Flag,
     pub visible: Option<bool>,
-    // TODO in the future we should make this one, each challenge can belong to multiple groups
-    // and if a group is marked as a build group it will be excluded by default.
     pub group: Option<String>,
+    // excluded from builds unless picked explicitly, and usable as a tag when picking
     pub build_group: Option<String>,
+    // shown to players and filterable, also used to select challenges to build
+    pub tags: Option<Vec<String>>,
+    pub difficulty: Option<String>,
+    // utc, hidden from players until then
+    pub release_at: Option<NaiveDateTime>,
     pub category: String,
     pub points: PointRange,
     pub provide: Option<Vec<Attachment>>,
@@ -43,6 +51,7 @@ pub struct Challenge {
     #[serde(default, skip_serializing_if = "is_zero")]
     pub bump_seed: u64,
     pub host: Option<String>,
+    pub solve: Option<Solve>,
 }
 
 fn is_zero(x: &u64) -> bool {
@@ -57,6 +66,9 @@ fn default_strategy() -> DeploymentStrategy {
 pub struct PointRange {
     pub min: i32,
     pub max: i32,
+    // overrides the decay of the event's scoring formula
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decay: Option<f64>,
 }
 
 impl Challenge {
@@ -68,57 +80,77 @@ impl Challenge {
         }
     }
 
-    pub async fn push_ct(&self, ctx: &DeployableContext, ct: &str) -> Result<()> {
+    pub fn solve_image_id(&self, ctx: &DeployableContext) -> String {
+        format!("{}/{}{}-solve", ctx.repo, ctx.image_prefix, self.id)
+    }
+
+    async fn push_image(&self, ctx: &DeployableContext, image: &str, name: &str) -> Result<()> {
         // TODO: support credentials
         let mut push = ctx.docker.push_image(
-            &self.image_id(ctx, ct),
+            image,
             None::<bollard::query_parameters::PushImageOptions>,
             ctx.docker_credentials.clone(),
         );
 
         while let Some(push_step) = push.next().await {
             let push_step = push_step.context("Docker image push error")?;
-            info!("{:?}", push_step);
+            info!("[{}/{}] {:?}", self.id, name, push_step);
         }
 
         Ok(())
     }
 
+    pub async fn push_ct(&self, ctx: &DeployableContext, ct: &str) -> Result<()> {
+        self.push_image(ctx, &self.image_id(ctx, ct), ct).await
+    }
+
+    pub async fn push_solve(&self, ctx: &DeployableContext) -> Result<()> {
+        self.push_image(ctx, &self.solve_image_id(ctx), "solve")
+            .await
+    }
+
     pub async fn push(&self, ctx: &DeployableContext) -> Result<()> {
         let Some(container) = &self.container else {
             return Ok(());
         };
 
         for ct in container.keys() {
-            self.push_ct(ctx, &ct).await?;
+            self.push_ct(ctx, ct).await?;
         }
 
         Ok(())
     }
 
-    pub async fn pull_ct(&self, ctx: &DeployableContext, ct: &str) -> Result<()> {
-        let options = CreateImageOptionsBuilder::new()
-            .from_image(&self.image_id(ctx, ct))
-            .build();
+    async fn pull_image(&self, ctx: &DeployableContext, image: &str, name: &str) -> Result<()> {
+        let options = CreateImageOptionsBuilder::new().from_image(image).build();
         let mut pull = ctx
             .docker
             .create_image(Some(options), None, ctx.docker_credentials.clone());
 
         while let Some(pull_step) = pull.next().await {
             let pull_step = pull_step.context("Docker image pull error")?;
-            info!("{:?}", pull_step);
+            info!("[{}/{}] {:?}", self.id, name, pull_step);
         }
 
         Ok(())
     }
 
+    pub async fn pull_ct(&self, ctx: &DeployableContext, ct: &str) -> Result<()> {
+        self.pull_image(ctx, &self.image_id(ctx, ct), ct).await
+    }
+
+    pub async fn pull_solve(&self, ctx: &DeployableContext) -> Result<()> {
+        self.pull_image(ctx, &self.solve_image_id(ctx), "solve")
+            .await
+    }
+
     pub async fn pull(&self, ctx: &DeployableContext) -> Result<()> {
         let Some(container) = &self.container else {
             return Ok(());
         };
 
         for ct in container.keys() {
-            self.pull_ct(ctx, &ct).await?;
+            self.pull_ct(ctx, ct).await?;
         }
 
         Ok(())
@@ -139,15 +171,84 @@ pub enum Attachment {
     Named {
         file: PathBuf,
         r#as: String,
+        name: Option<String>,
     },
     Archive {
         dir: PathBuf,
         // without archive extension
         #[serde(default = "default_archive_name")]
         r#as: String,
+        // gitignore syntax
         #[serde(default)]
         exclude: Option<Vec<PathBuf>>,
+        #[serde(default)]
+        format: ArchiveFormat,
+        name: Option<String>,
     },
+    /// Link to something hosted elsewhere, nothing is uploaded.
+    Url {
+        url: String,
+        r#as: String,
+        name: Option<String>,
+    },
+    /// File copied out of an image built from `build`, e.g. a compiled binary.
+    Generated {
+        build: PathBuf,
+        // dockerfile stage to build
+        target: Option<String>,
+        // absolute path of the file inside the image
+        path: String,
+        // defaults to the file name of path
+        r#as: Option<String>,
+        name: Option<String>,
+    },
+}
+
+impl Attachment {
+    /// File name players download the attachment as.
+    pub fn file_name(&self) -> Result<String> {
+        Ok(match self {
+            Self::File(path) => path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| eyre!("Invalid attachment path {}", path.display()))?
+                .to_owned(),
+            Self::Named { r#as, .. } | Self::Url { r#as, .. } => r#as.clone(),
+            Self::Archive { r#as, format, .. } => format!("{as}.{}", format.extension()),
+            Self::Generated { r#as, path, .. } => match r#as {
+                Some(r#as) => r#as.clone(),
+                None => path
+                    .rsplit('/')
+                    .find(|p| !p.is_empty())
+                    .ok_or_else(|| eyre!("Invalid generated attachment path {path}"))?
+                    .to_owned(),
+            },
+        })
+    }
+
+    /// Name shown to players instead of the file name.
+    pub fn display_name(&self) -> Option<&str> {
+        match self {
+            Self::File(_) => None,
+            Self::Named { name, .. }
+            | Self::Archive { name, .. }
+            | Self::Url { name, .. }
+            | Self::Generated { name, .. } => name.as_deref(),
+        }
+    }
+}
+
+/// An attachment after it has been pushed, as stored in the platform db.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UploadedAttachment {
+    pub url: String,
+    // unset for url attachments
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
 }
 
 fn default_archive_name() -> String {
@@ -165,9 +266,15 @@ pub struct Container {
     pub expose: Option<HashMap<u16, ExposeType>>,
     pub cap_add: Option<Vec<String>>,
     pub privileged: Option<bool>,
+    /// `--build-arg`s passed to docker build
+    pub build_args: Option<HashMap<String, String>>,
+    /// Dockerfile stage to build
+    pub target: Option<String>,
+    /// Platform to build for, e.g. `linux/amd64`
+    pub platform: Option<String>,
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum DeploymentStrategy {
     Static,
@@ -308,6 +415,55 @@ impl DeployableChallenge {
         Ok(Self { chall, root })
     }
 
+    pub fn flag(&self) -> Result<String> {
+        Ok(match &self.chall.flag {
+            Flag::Raw(flag) => flag.clone(),
+            Flag::File { file } => fs::read_to_string(self.root.join(file))
+                .with_context(|| format!("Failed to read flag file {}", file.display()))?
+                .trim()
+                .to_owned(),
+        })
+    }
+
+    /// Hashes the build context (respecting `.dockerignore`) and config of a container.
+    pub fn context_hash(&self, ct: &str) -> Result<Option<String>> {
+        let Some(chall_container) = self.chall.container.as_ref().and_then(|c| c.get(ct)) else {
+            return Ok(None);
+        };
+
+        let mut hasher = Sha256::new();
+        // toml tables are ordered, so this is deterministic
+        hasher.update(toml::to_string(&toml::Value::try_from(chall_container)?)?);
+        hasher.update([0]);
+        BuildContext::new(self.root.join(&chall_container.build))?.hash_into(&mut hasher)?;
+
+        Ok(Some(format!("{:x}", hasher.finalize())))
+    }
+
+    /// Context hash of the image currently in the registry, if there is one. Only the manifest
+    /// and config are fetched, not the layers.
+    pub async fn registry_context_hash(
+        &self,
+        ctx: &DeployableContext,
+        ct: &str,
+    ) -> Result<Option<String>> {
+        let image = self.chall.image_id(ctx, ct);
+        match registry::image_labels(&image, ctx.docker_credentials.as_ref()).await {
+            Ok(labels) => Ok(labels.and_then(|mut l| l.remove(CONTEXT_HASH_LABEL))),
+            Err(e) => {
+                debug!("failed to read the labels of {image}: {e:?}");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Whether the context hash of a container differs from the image in the registry.
+    pub async fn is_changed(&self, ctx: &DeployableContext, ct: &str) -> Result<bool> {
+        let local = self.context_hash(ct)?;
+        let remote = self.registry_context_hash(ctx, ct).await?;
+        Ok(local.is_none() || local != remote)
+    }
+
     pub async fn build_ct(
         &self,
         ctx: &DeployableContext,
@@ -321,40 +477,40 @@ impl DeployableChallenge {
             return Ok(None);
         };
 
-        let tmp = TempDir::new(&self.chall.id)?;
-        let context_tar_path = tmp.path().join("docker.tar");
-        {
-            // ugh
-            let tar_file = StdFile::create(&context_tar_path)?;
-            let mut tar_ = tar::Builder::new(tar_file);
-            tar_.sparse(false);
-            let context_dir_path = self.root.join(&chall_container.build);
-            tar_.append_dir_all(".", &context_dir_path)
-                .with_context(|| {
-                    format!(
-                        "Failed to read Docker context {}",
-                        context_dir_path.display()
-                    )
-                })?;
-            tar_.finish()?;
+        let context = BuildContext::new(self.root.join(&chall_container.build))?;
+
+        let mut labels = HashMap::new();
+        if let Some(hash) = self.context_hash(ct)? {
+            labels.insert(CONTEXT_HASH_LABEL, hash);
         }
 
-        let options = bollard::query_parameters::BuildImageOptionsBuilder::new()
+        let mut options = bollard::query_parameters::BuildImageOptionsBuilder::new()
             // FIXME(ani): idk if it's ideal to tag the image with the repo name in build
             .t(&self.chall.image_id(ctx, ct))
+            .labels(&labels)
             .forcerm(true)
-            .rm(true)
-            .build();
-        let tar_file_r = File::open(&context_tar_path).await?;
-        let tar_file_r = ReaderStream::new(tar_file_r);
-        let mut build =
-            ctx.docker
-                .build_image(options, None, Some(bollard::body_try_stream(tar_file_r)));
+            .rm(true);
+        if let Some(build_args) = &chall_container.build_args {
+            options = options.buildargs(build_args);
+        }
+        if let Some(target) = &chall_container.target {
+            options = options.target(target);
+        }
+        if let Some(platform) = &chall_container.platform {
+            options = options.platform(platform);
+        }
+
+        let context_stream = context.into_tar_stream().map_ok(Into::into);
+        let mut build = ctx.docker.build_image(
+            options.build(),
+            None,
+            Some(bollard::body_try_stream(context_stream)),
+        );
         let mut build_infos = vec![];
         while let Some(build_step) = build.next().await {
             let build_step = build_step.context("Docker build image error")?;
             if let Some(stream) = &build_step.stream {
-                info!("{}", stream);
+                info!("[{}/{}] {}", self.chall.id, ct, stream.trim_end());
             }
             build_infos.push(build_step);
         }
@@ -372,7 +528,7 @@ impl DeployableChallenge {
 
         let mut out = vec![];
         for ct in container.keys() {
-            if let Some(b) = self.build_ct(ctx, &ct).await? {
+            if let Some(b) = self.build_ct(ctx, ct).await? {
                 out.push(b);
             }
         }
@@ -388,78 +544,246 @@ impl DeployableChallenge {
         self.chall.push(ctx).await
     }
 
-    pub async fn push_attachments(
+    /// Builds `build` (relative to the challenge root) into `tag`, for images that aren't
+    /// challenge containers.
+    async fn build_aux_image(
         &self,
-        client: &Client,
-        bucket: String,
-    ) -> Result<HashMap<String, String>> {
-        if self.chall.provide.is_none() {
-            return Ok(HashMap::new());
+        docker: &bollard::Docker,
+        build: &Path,
+        tag: &str,
+        target: Option<&str>,
+        log_name: &str,
+    ) -> Result<()> {
+        let context = BuildContext::new(self.root.join(build))?;
+        let mut options = bollard::query_parameters::BuildImageOptionsBuilder::new()
+            .t(tag)
+            .forcerm(true)
+            .rm(true);
+        if let Some(target) = target {
+            options = options.target(target);
         }
 
-        let mut hm = HashMap::new();
-        for attachment in self.chall.provide.as_ref().unwrap() {
-            let (name, data) = match attachment {
-                Attachment::File(path) => {
-                    let name = path.file_name().unwrap().to_str().unwrap().to_owned();
-                    let data = fs::read_to_string(self.root.join(path))?;
-                    (name, Vec::from(data.as_bytes()))
-                }
-                Attachment::Named { file, r#as } => {
-                    let data = fs::read(self.root.join(file))?;
-                    (r#as.clone(), data)
-                }
-                Attachment::Archive { dir, r#as, exclude } => {
-                    let tmp = TempDir::new(&self.chall.id)?;
-                    let tar_path = tmp.path().join("chall.tar.gz");
+        let context_stream = context.into_tar_stream().map_ok(Into::into);
+        let mut build_ = docker.build_image(
+            options.build(),
+            None,
+            Some(bollard::body_try_stream(context_stream)),
+        );
+        while let Some(build_step) = build_.next().await {
+            let build_step = build_step.context("Docker build image error")?;
+            if let Some(stream) = &build_step.stream {
+                info!("[{}/{}] {}", self.chall.id, log_name, stream.trim_end());
+            }
+        }
 
-                    // ugh
-                    {
-                        let tar_file = StdFile::create(&tar_path)?;
-                        let enc = GzEncoder::new(tar_file, Compression::default());
-                        let mut tar_ = tar::Builder::new(enc);
-                        // TODO actually support exclude
-                        tar_.append_dir_all(r#as, self.root.join(dir))?;
+        Ok(())
+    }
 
-                        tar_.finish()?;
-                    }
+    /// Builds the solver image, if the challenge has one.
+    pub async fn build_solve(&self, ctx: &DeployableContext) -> Result<bool> {
+        let Some(solve) = &self.chall.solve else {
+            return Ok(false);
+        };
 
-                    let mut buffer = Vec::new();
-                    StdFile::open(&tar_path)?.read_to_end(&mut buffer)?;
+        let tag = self.chall.solve_image_id(ctx);
+        self.build_aux_image(&ctx.docker, &solve.build, &tag, None, "solve")
+            .await?;
+        Ok(true)
+    }
+
+    /// Builds the image for a generated attachment and copies `path` out of it.
+    pub async fn generate_attachment(
+        &self,
+        docker: &bollard::Docker,
+        build: &Path,
+        target: Option<&str>,
+        path: &str,
+    ) -> Result<Vec<u8>> {
+        let image = format!("nerine-attachment-{}", self.chall.id);
+        self.build_aux_image(docker, build, &image, target, "attachments")
+            .await?;
+
+        // never started, only needed to copy files out of the image
+        let container = docker
+            .create_container(
+                None::<bollard::query_parameters::CreateContainerOptions>,
+                bollard::models::ContainerCreateBody {
+                    image: Some(image.clone()),
+                    ..Default::default()
+                },
+            )
+            .await?
+            .id;
+        let archive = docker
+            .download_from_container(
+                &container,
+                Some(
+                    bollard::query_parameters::DownloadFromContainerOptionsBuilder::new()
+                        .path(path)
+                        .build(),
+                ),
+            )
+            .try_fold(vec![], |mut acc, chunk| async move {
+                acc.extend_from_slice(&chunk);
+                Ok(acc)
+            })
+            .await;
+        docker
+            .remove_container(
+                &container,
+                Some(
+                    bollard::query_parameters::RemoveContainerOptionsBuilder::new()
+                        .v(true)
+                        .force(true)
+                        .build(),
+                ),
+            )
+            .await
+            .ok();
+        // only built to copy the attachment out of, so it would just pile up
+        docker
+            .remove_image(
+                &image,
+                Some(
+                    bollard::query_parameters::RemoveImageOptionsBuilder::new()
+                        .force(true)
+                        .build(),
+                ),
+                None,
+            )
+            .await
+            .ok();
+        let archive =
+            archive.with_context(|| format!("Failed to copy {path} out of generated image"))?;
+
+        let mut tar_ = tar::Archive::new(archive.as_slice());
+        let mut entry = tar_
+            .entries()?
+            .next()
+            .ok_or_else(|| eyre!("{path} not found in generated image"))??;
+        if entry.header().entry_type() != tar::EntryType::Regular {
+            return Err(eyre!(
+                "Generated attachment {path} must be a file, archive it in the Dockerfile"
+            ));
+        }
+        let mut data = vec![];
+        entry.read_to_end(&mut data)?;
+
+        Ok(data)
+    }
 
-                    (format!("{as}.tar.gz"), buffer)
+    /// Contents of an attachment, `None` for url attachments which aren't uploaded.
+    async fn attachment_data(
+        &self,
+        docker: &bollard::Docker,
+        attachment: &Attachment,
+    ) -> Result<Option<Vec<u8>>> {
+        Ok(Some(match attachment {
+            Attachment::File(file) | Attachment::Named { file, .. } => {
+                fs::read(self.root.join(file))
+                    .with_context(|| format!("Failed to read {}", file.display()))?
+            }
+            Attachment::Archive {
+                dir,
+                r#as,
+                exclude,
+                format,
+                ..
+            } => {
+                let exclude = IgnoreRules::parse_gitignore(
+                    exclude.iter().flatten().map(|p| p.to_string_lossy()),
+                )?;
+                create_archive(&self.root.join(dir), r#as, &exclude, *format)
+                    .with_context(|| format!("Failed to archive {}", dir.display()))?
+            }
+            Attachment::Url { .. } => return Ok(None),
+            Attachment::Generated {
+                build,
+                target,
+                path,
+                ..
+            } => {
+                self.generate_attachment(docker, build, target.as_deref(), path)
+                    .await?
+            }
+        }))
+    }
+
+    /// Contents of every attachment in `provide` order, `None` for url attachments. Generated
+    /// attachments are built here, so hold on to the result instead of reading them again.
+    pub async fn read_attachments(&self, docker: &bollard::Docker) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut contents = vec![];
+        for attachment in self.chall.provide.iter().flatten() {
+            contents.push(self.attachment_data(docker, attachment).await?);
+        }
+        Ok(contents)
+    }
+
+    /// What each attachment would be stored as, without uploading anything. Url attachments
+    /// map to their url, everything else to the sha256 of its contents.
+    pub fn attachment_digests(
+        &self,
+        contents: &[Option<Vec<u8>>],
+    ) -> Result<HashMap<String, String>> {
+        let mut hm = HashMap::new();
+        for (attachment, data) in self.chall.provide.iter().flatten().zip(contents) {
+            let digest = match attachment {
+                Attachment::Url { url, .. } => url.clone(),
+                _ => format!("{:x}", Sha256::digest(data.as_deref().unwrap_or_default())),
+            };
+            hm.insert(attachment.file_name()?, digest);
+        }
+        Ok(hm)
+    }
+
+    /// Uploads the output of `read_attachments`.
+    pub async fn push_attachments(
+        &self,
+        contents: Vec<Option<Vec<u8>>>,
+        storage: &impl AttachmentStorage,
+    ) -> Result<HashMap<String, UploadedAttachment>> {
+        let mut hm = HashMap::new();
+        for (attachment, data) in self.chall.provide.iter().flatten().zip(contents) {
+            let name = attachment.file_name()?;
+            let display_name = attachment.display_name().map(ToOwned::to_owned);
+            let Some(data) = data else {
+                if let Attachment::Url { url, .. } = attachment {
+                    hm.insert(
+                        name,
+                        UploadedAttachment {
+                            url: url.clone(),
+                            sha256: None,
+                            size: None,
+                            display_name,
+                        },
+                    );
                 }
+                continue;
             };
 
-            let upload_type =
-                UploadType::Simple(Media::new(format!("{}/{}", self.chall.id, &name)));
-
-            let uploaded = client
-                .upload_object(
-                    &UploadObjectRequest {
-                        bucket: bucket.clone(),
-                        ..Default::default()
-                    },
-                    data,
-                    &upload_type,
-                )
-                .await?;
-
-            let url_for_download = client
-                .signed_url(
-                    &bucket,
-                    &uploaded.name,
-                    None,
-                    None,
-                    SignedURLOptions {
-                        expires: Duration::from_secs(604800),
-                        ..Default::default()
-                    },
-                )
-                .await?;
+            let sha256 = format!("{:x}", Sha256::digest(&data));
+            let size = data.len() as u64;
+            // keyed by content so links to old versions keep working and identical attachments
+            // are only stored once. archives are deterministic, so this also skips unchanged ones
+            let key = format!("{sha256}/{name}");
+
+            if storage.stored_sha256(&key).await?.as_ref() == Some(&sha256) {
+                info!("[{}] {} is already uploaded, skipping", self.chall.id, name);
+            } else {
+                storage.upload(&key, data, &sha256).await?;
+            }
 
-            hm.insert(name, url_for_download);
+            let url = storage.download_url(&key).await?;
+            hm.insert(
+                name,
+                UploadedAttachment {
+                    url,
+                    sha256: Some(sha256),
+                    size: Some(size),
+                    display_name,
+                },
+            );
         }
-        return Ok(hm);
+        Ok(hm)
     }
 }
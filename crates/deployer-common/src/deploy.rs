@@ -0,0 +1,111 @@
+//! Container layout shared by the deployer and local runs, so a challenge behaves the same
+//! wherever it is started.
+use std::collections::HashMap;
+
+use bollard::secret::{
+    ContainerCreateBody, EndpointSettings, HostConfig, NetworkingConfig, PortBinding,
+};
+use sha2::Digest;
+
+use crate::challenge::{Challenge, DeployableContext, DeploymentStrategy};
+
+pub fn calculate_container_name(
+    chall_id: &str,
+    strategy: DeploymentStrategy,
+    ct: &str,
+    team_id: Option<i32>,
+) -> String {
+    match strategy {
+        DeploymentStrategy::Static => format!("{}-container-{}", chall_id, ct),
+        DeploymentStrategy::Instanced => {
+            format!("{}-team-{}-container-{}", chall_id, team_id.unwrap(), ct)
+        }
+    }
+}
+
+pub fn calculate_network_name(
+    chall_id: &str,
+    strategy: DeploymentStrategy,
+    team_id: Option<i32>,
+) -> String {
+    match strategy {
+        DeploymentStrategy::Static => format!("{}-network", chall_id),
+        DeploymentStrategy::Instanced => format!("{}-team-{}-network", chall_id, team_id.unwrap()),
+    }
+}
+
+pub fn calculate_static_tcp_port(
+    chall_id: &str,
+    container_name: &str,
+    port: u16,
+    bump: u64,
+) -> u16 {
+    let h = {
+        use std::io::Write;
+
+        let mut hasher = sha2::Sha256::new();
+        write!(hasher, "{}/{}/{},{}", chall_id, container_name, port, bump,).unwrap();
+        hasher.finalize()
+    };
+    // take first 16 bits
+    let n = u16::from_le_bytes(h[..2].try_into().unwrap());
+    n.saturating_add(1025)
+}
+
+/// Config for container `ct` of `chall`, attached to `network_name` under its container name
+/// as an alias. `tcp_ports` maps exposed container ports to the host ports they are bound to.
+pub fn container_body(
+    chall: &Challenge,
+    ctx: &DeployableContext,
+    ct: &str,
+    network_name: &str,
+    tcp_ports: &HashMap<u16, u16>,
+) -> ContainerCreateBody {
+    let chall_container = &chall.container.as_ref().unwrap()[ct];
+
+    ContainerCreateBody {
+        env: chall_container.env.as_ref().map(|h| {
+            h.iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+        }),
+        image: Some(chall.image_id(ctx, ct)),
+        networking_config: Some(NetworkingConfig {
+            endpoints_config: Some(HashMap::from([(
+                network_name.to_owned(),
+                EndpointSettings {
+                    aliases: Some(vec![ct.to_owned()]),
+                    ..Default::default()
+                },
+            )])),
+        }),
+        exposed_ports: Some(
+            tcp_ports
+                .keys()
+                .map(|k| (format!("{}/tcp", k), Default::default()))
+                .collect::<HashMap<_, _>>(),
+        ),
+        host_config: Some(HostConfig {
+            nano_cpus: chall_container.limits.cpu, // nanocpus (10 ^ -9 cpus)
+            memory: chall_container.limits.mem,    // bytes
+            port_bindings: Some(
+                tcp_ports
+                    .iter()
+                    .map(|(p1, p2)| {
+                        (
+                            format!("{}/tcp", p1),
+                            Some(vec![PortBinding {
+                                host_ip: Some("0.0.0.0".to_owned()),
+                                host_port: Some(format!("{}", p2)),
+                            }]),
+                        )
+                    })
+                    .collect::<HashMap<_, _>>(),
+            ),
+            cap_add: chall_container.cap_add.clone(),
+            privileged: chall_container.privileged,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
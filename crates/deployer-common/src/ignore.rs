@@ -0,0 +1,179 @@
+use eyre::{Context, Result};
+use glob::{MatchOptions, Pattern};
+use std::{fs, io, path::Path};
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    pattern: Pattern,
+    negate: bool,
+    // gitignore patterns ending in a slash only match directories
+    dir_only: bool,
+}
+
+impl IgnoreRule {
+    fn parse(line: &str, gitignore: bool) -> Result<Option<Self>> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let (negate, line) = match line.strip_prefix('!') {
+            Some(l) => (true, l.trim()),
+            None => (false, line),
+        };
+        // gitignore patterns without a slash in them match at any depth. docker cleans the
+        // trailing slash away, so there `foo/` matches files too
+        let anchored = !gitignore || line.trim_end_matches('/').contains('/');
+        let dir_only = gitignore && line.ends_with('/');
+        let line = line.trim_start_matches("./").trim_matches('/');
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        let pattern = if anchored {
+            line.to_owned()
+        } else {
+            format!("**/{line}")
+        };
+        Ok(Some(Self {
+            pattern: Pattern::new(&pattern)
+                .with_context(|| format!("Invalid ignore pattern {line}"))?,
+            negate,
+            dir_only,
+        }))
+    }
+
+    // like docker and git, a rule matching a directory also matches everything inside of it
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        path.ancestors()
+            // every ancestor is a directory, only the path itself might not be
+            .skip(usize::from(self.dir_only && !is_dir))
+            .filter(|p| !p.as_os_str().is_empty())
+            .filter_map(|p| p.to_str())
+            .any(|p| self.pattern.matches_with(p, MATCH_OPTIONS))
+    }
+}
+
+/// Ordered list of ignore patterns, where later patterns take precedence and `!` re-includes.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreRules {
+    fn parse<S: AsRef<str>>(lines: impl IntoIterator<Item = S>, gitignore: bool) -> Result<Self> {
+        let mut rules = vec![];
+        for line in lines {
+            if let Some(rule) = IgnoreRule::parse(line.as_ref(), gitignore)? {
+                rules.push(rule);
+            }
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// `.dockerignore` syntax, every pattern is relative to the root.
+    pub fn parse_dockerignore(contents: &str) -> Result<Self> {
+        Self::parse(contents.lines(), false)
+    }
+
+    /// `.gitignore` syntax, patterns without a `/` match files at any depth.
+    pub fn parse_gitignore<S: AsRef<str>>(lines: impl IntoIterator<Item = S>) -> Result<Self> {
+        Self::parse(lines, true)
+    }
+
+    /// Reads `.dockerignore` from `dir`, treating a missing file as empty.
+    pub fn from_dockerignore_dir(dir: &Path) -> Result<Self> {
+        match fs::read_to_string(dir.join(".dockerignore")) {
+            Ok(contents) => Self::parse_dockerignore(&contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).context("Failed to read .dockerignore"),
+        }
+    }
+
+    /// `path` is relative to the root.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.negate == ignored && rule.matches(path, is_dir) {
+                ignored = !rule.negate;
+            }
+        }
+        ignored
+    }
+
+    /// Whether ignored directories can be skipped entirely, which is only the case if nothing
+    /// can be re-included.
+    pub fn can_prune(&self) -> bool {
+        !self.rules.iter().any(|r| r.negate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gitignore(lines: &[&str]) -> IgnoreRules {
+        IgnoreRules::parse_gitignore(lines).unwrap()
+    }
+
+    fn dockerignore(contents: &str) -> IgnoreRules {
+        IgnoreRules::parse_dockerignore(contents).unwrap()
+    }
+
+    #[test]
+    fn anchoring() {
+        let rules = gitignore(&["*.pyc", "/build", "docs/*.md"]);
+        assert!(rules.is_ignored(Path::new("a.pyc"), false));
+        assert!(rules.is_ignored(Path::new("src/deep/a.pyc"), false));
+        assert!(rules.is_ignored(Path::new("build"), true));
+        assert!(rules.is_ignored(Path::new("build/out.o"), false));
+        assert!(!rules.is_ignored(Path::new("src/build"), true));
+        assert!(rules.is_ignored(Path::new("docs/a.md"), false));
+        assert!(!rules.is_ignored(Path::new("src/docs/a.md"), false));
+        assert!(!rules.is_ignored(Path::new("docs/sub/a.md"), false));
+
+        // dockerignore patterns are always relative to the root
+        let rules = dockerignore("*.pyc\n./build\n");
+        assert!(rules.is_ignored(Path::new("a.pyc"), false));
+        assert!(!rules.is_ignored(Path::new("src/a.pyc"), false));
+        assert!(rules.is_ignored(Path::new("build/out.o"), false));
+    }
+
+    #[test]
+    fn negation() {
+        let rules = gitignore(&["*.log", "!keep.log", "# comment", ""]);
+        assert!(rules.is_ignored(Path::new("a.log"), false));
+        assert!(!rules.is_ignored(Path::new("keep.log"), false));
+        assert!(!rules.is_ignored(Path::new("logs/keep.log"), false));
+        assert!(!rules.can_prune());
+
+        // later rules win
+        let rules = dockerignore("!a.txt\n*.txt\n");
+        assert!(rules.is_ignored(Path::new("a.txt"), false));
+
+        assert!(gitignore(&["*.log", "build/"]).can_prune());
+    }
+
+    #[test]
+    fn dir_only() {
+        let rules = gitignore(&["out/", "/cache/"]);
+        assert!(rules.is_ignored(Path::new("out"), true));
+        assert!(!rules.is_ignored(Path::new("out"), false));
+        assert!(rules.is_ignored(Path::new("out/a.txt"), false));
+        assert!(rules.is_ignored(Path::new("src/out"), true));
+        assert!(!rules.is_ignored(Path::new("src/out"), false));
+        assert!(rules.is_ignored(Path::new("cache/a"), false));
+        assert!(!rules.is_ignored(Path::new("cache"), false));
+
+        // docker drops the trailing slash
+        let rules = dockerignore("out/\n");
+        assert!(rules.is_ignored(Path::new("out"), false));
+    }
+}
@@ -1 +1,8 @@
+pub mod archive;
+pub mod build_context;
 pub mod challenge;
+pub mod deploy;
+pub mod ignore;
+pub mod registry;
+pub mod solve;
+pub mod storage;
@@ -0,0 +1,297 @@
+// just enough of the registry api to read an image's labels without pulling its layers
+use std::collections::HashMap;
+
+use bollard::auth::DockerCredentials;
+use eyre::{Context, Result, eyre};
+use reqwest::{
+    Client, RequestBuilder, Response, StatusCode,
+    header::{ACCEPT, WWW_AUTHENTICATE},
+};
+use serde::Deserialize;
+
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+#[derive(Debug, PartialEq)]
+struct ImageRef {
+    // base url, e.g. https://ghcr.io
+    registry: String,
+    repository: String,
+    // tag or digest
+    reference: String,
+}
+
+/// Splits an image name like docker does, `user/app` is `docker.io/user/app:latest`.
+fn parse_image_ref(image: &str) -> ImageRef {
+    let (name, reference) = match image.rsplit_once('@') {
+        Some((name, digest)) => (name, digest.to_owned()),
+        None => match image.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => (name, tag.to_owned()),
+            _ => (image, "latest".to_owned()),
+        },
+    };
+
+    let (registry, repository) = match name.split_once('/') {
+        Some((host, rest)) if host.contains(['.', ':']) || host == "localhost" => {
+            (host.to_owned(), rest.to_owned())
+        }
+        _ => ("docker.io".to_owned(), name.to_owned()),
+    };
+    let (registry, repository) = if registry == "docker.io" {
+        let repository = if repository.contains('/') {
+            repository
+        } else {
+            format!("library/{repository}")
+        };
+        ("https://registry-1.docker.io".to_owned(), repository)
+    } else if registry.starts_with("localhost") || registry.starts_with("127.0.0.1") {
+        (format!("http://{registry}"), repository)
+    } else {
+        (format!("https://{registry}"), repository)
+    };
+
+    ImageRef {
+        registry,
+        repository,
+        reference,
+    }
+}
+
+/// `key="value"` pairs of a `WWW-Authenticate` challenge, after the scheme.
+fn parse_challenge(params: &str) -> HashMap<String, String> {
+    let mut out = HashMap::new();
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_lowercase();
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        out.insert(key, value.to_owned());
+        rest = after;
+    }
+    out
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+struct Registry<'a> {
+    client: Client,
+    credentials: Option<&'a DockerCredentials>,
+    // bearer token, once the registry asked for one
+    token: Option<String>,
+}
+
+impl Registry<'_> {
+    fn basic_auth(&self, req: RequestBuilder) -> RequestBuilder {
+        match self.credentials {
+            Some(DockerCredentials {
+                username: Some(username),
+                password,
+                ..
+            }) => req.basic_auth(username, password.as_ref()),
+            _ => req,
+        }
+    }
+
+    fn request(&self, url: &str, accept: &str) -> RequestBuilder {
+        let req = self.client.get(url).header(ACCEPT, accept);
+        match &self.token {
+            Some(token) => req.bearer_auth(token),
+            None => self.basic_auth(req),
+        }
+    }
+
+    async fn get(&mut self, url: &str, accept: &str) -> Result<Response> {
+        let res = self.request(url, accept).send().await?;
+        if res.status() != StatusCode::UNAUTHORIZED {
+            return Ok(res);
+        }
+        let challenge = res
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default()
+            .to_owned();
+        let Some(params) = challenge.strip_prefix("Bearer ") else {
+            // basic auth was already tried
+            return Ok(res);
+        };
+
+        let params = parse_challenge(params);
+        let realm = params
+            .get("realm")
+            .ok_or_else(|| eyre!("Registry auth challenge without a realm: {challenge}"))?;
+        let query: Vec<_> = params
+            .iter()
+            .filter(|(k, _)| *k == "service" || *k == "scope")
+            .collect();
+        let token: TokenResponse = self
+            .basic_auth(self.client.get(realm).query(&query))
+            .send()
+            .await?
+            .error_for_status()
+            .context("Failed to get a registry token")?
+            .json()
+            .await?;
+        self.token = token.token.or(token.access_token);
+
+        Ok(self.request(url, accept).send().await?)
+    }
+
+    async fn get_json<T: for<'de> Deserialize<'de>>(
+        &mut self,
+        url: &str,
+        accept: &str,
+    ) -> Result<Option<T>> {
+        let res = self.get(url, accept).await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(
+            res.error_for_status()
+                .with_context(|| format!("Failed to get {url}"))?
+                .json()
+                .await?,
+        ))
+    }
+}
+
+#[derive(Deserialize)]
+struct Platform {
+    architecture: String,
+    os: String,
+}
+
+#[derive(Deserialize)]
+struct Descriptor {
+    digest: String,
+    platform: Option<Platform>,
+}
+
+// image indexes and manifests, told apart by which of the two is there
+#[derive(Deserialize)]
+struct Manifest {
+    manifests: Option<Vec<Descriptor>>,
+    config: Option<Descriptor>,
+}
+
+#[derive(Deserialize)]
+struct ImageConfig {
+    config: Option<ContainerConfig>,
+}
+
+#[derive(Deserialize)]
+struct ContainerConfig {
+    #[serde(rename = "Labels")]
+    labels: Option<HashMap<String, String>>,
+}
+
+fn docker_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        arch => arch,
+    }
+}
+
+/// Labels of `image` in its registry, `None` if it isn't there.
+pub async fn image_labels(
+    image: &str,
+    credentials: Option<&DockerCredentials>,
+) -> Result<Option<HashMap<String, String>>> {
+    let image = parse_image_ref(image);
+    let mut registry = Registry {
+        client: Client::new(),
+        credentials,
+        token: None,
+    };
+    let base = format!("{}/v2/{}", image.registry, image.repository);
+
+    let mut url = format!("{base}/manifests/{}", image.reference);
+    let config = loop {
+        let Some(manifest) = registry.get_json::<Manifest>(&url, MANIFEST_TYPES).await? else {
+            return Ok(None);
+        };
+        if let Some(config) = manifest.config {
+            break config;
+        }
+
+        // multi-platform, the labels are the same for every platform built from one context
+        let manifests = manifest.manifests.unwrap_or_default();
+        let platform = |d: &&Descriptor| {
+            d.platform
+                .as_ref()
+                .is_some_and(|p| p.os == "linux" && p.architecture == docker_arch())
+        };
+        // attestations are listed as unknown/unknown
+        let known = |d: &&Descriptor| d.platform.as_ref().is_none_or(|p| p.os != "unknown");
+        let Some(descriptor) = manifests
+            .iter()
+            .find(platform)
+            .or_else(|| manifests.iter().find(known))
+        else {
+            return Err(eyre!("{url} lists no usable manifests"));
+        };
+        url = format!("{base}/manifests/{}", descriptor.digest);
+    };
+
+    let config: Option<ImageConfig> = registry
+        .get_json(&format!("{base}/blobs/{}", config.digest), "*/*")
+        .await?;
+    Ok(Some(
+        config
+            .and_then(|c| c.config)
+            .and_then(|c| c.labels)
+            .unwrap_or_default(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_ref(registry: &str, repository: &str, reference: &str) -> ImageRef {
+        ImageRef {
+            registry: registry.to_owned(),
+            repository: repository.to_owned(),
+            reference: reference.to_owned(),
+        }
+    }
+
+    #[test]
+    fn image_refs() {
+        assert_eq!(
+            parse_image_ref("nginx"),
+            image_ref("https://registry-1.docker.io", "library/nginx", "latest")
+        );
+        assert_eq!(
+            parse_image_ref("user/chall-web:v2"),
+            image_ref("https://registry-1.docker.io", "user/chall-web", "v2")
+        );
+        assert_eq!(
+            parse_image_ref("ghcr.io/org/ctf/chall"),
+            image_ref("https://ghcr.io", "org/ctf/chall", "latest")
+        );
+        assert_eq!(
+            parse_image_ref("localhost:5000/chall@sha256:abc"),
+            image_ref("http://localhost:5000", "chall", "sha256:abc")
+        );
+    }
+
+    #[test]
+    fn challenges() {
+        let params = parse_challenge(
+            r#"realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:a/b:pull,push""#,
+        );
+        assert_eq!(params["realm"], "https://auth.docker.io/token");
+        assert_eq!(params["service"], "registry.docker.io");
+        assert_eq!(params["scope"], "repository:a/b:pull,push");
+    }
+}
@@ -0,0 +1,135 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use bollard::{
+    query_parameters::{
+        CreateContainerOptionsBuilder, LogsOptionsBuilder, RemoveContainerOptionsBuilder,
+        StartContainerOptions, WaitContainerOptions,
+    },
+    secret::{ContainerCreateBody, EndpointSettings, NetworkingConfig},
+};
+use eyre::{Context, Result};
+use futures_util::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::challenge::{Challenge, DeployableContext};
+
+/// Solver image that is run against a deployment to check that the challenge works.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Solve {
+    /// Docker context of the solver
+    pub build: PathBuf,
+    pub env: Option<HashMap<String, String>>,
+    /// Seconds before the solver is killed
+    #[serde(default = "default_solve_timeout")]
+    pub timeout: u64,
+}
+
+fn default_solve_timeout() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SolveResult {
+    /// Whether the solver printed the flag.
+    pub passed: bool,
+    // none if the solver timed out
+    pub exit_code: Option<i64>,
+    pub output: String,
+}
+
+impl Challenge {
+    /// Runs the solver on `network_name`, where it can reach every container by its name.
+    /// `None` if the challenge has no solver.
+    pub async fn run_solve(
+        &self,
+        ctx: &DeployableContext,
+        network_name: &str,
+        flag: &str,
+    ) -> Result<Option<SolveResult>> {
+        let Some(solve) = &self.solve else {
+            return Ok(None);
+        };
+
+        let container_name = format!("{network_name}-solve");
+        let remove = || {
+            ctx.docker.remove_container(
+                &container_name,
+                Some(
+                    RemoveContainerOptionsBuilder::new()
+                        .v(true)
+                        .force(true)
+                        .build(),
+                ),
+            )
+        };
+
+        remove().await.ok();
+        ctx.docker
+            .create_container(
+                Some(
+                    CreateContainerOptionsBuilder::new()
+                        .name(&container_name)
+                        .build(),
+                ),
+                ContainerCreateBody {
+                    env: solve.env.as_ref().map(|h| {
+                        h.iter()
+                            .map(|(k, v)| format!("{}={}", k, v))
+                            .collect::<Vec<_>>()
+                    }),
+                    image: Some(self.solve_image_id(ctx)),
+                    networking_config: Some(NetworkingConfig {
+                        endpoints_config: Some(HashMap::from([(
+                            network_name.to_owned(),
+                            EndpointSettings::default(),
+                        )])),
+                    }),
+                    ..Default::default()
+                },
+            )
+            .await
+            .context("Failed to create solver container")?;
+
+        let res = async {
+            ctx.docker
+                .start_container(&container_name, None::<StartContainerOptions>)
+                .await?;
+
+            let mut wait = ctx
+                .docker
+                .wait_container(&container_name, None::<WaitContainerOptions>);
+            let exit_code =
+                match tokio::time::timeout(Duration::from_secs(solve.timeout), wait.next()).await {
+                    Ok(Some(Ok(res))) => Some(res.status_code),
+                    // non-zero exit codes are returned as errors
+                    Ok(Some(Err(bollard::errors::Error::DockerContainerWaitError {
+                        code,
+                        ..
+                    }))) => Some(code),
+                    Ok(Some(Err(e))) => return Err(e.into()),
+                    Ok(None) | Err(_) => None,
+                };
+
+            let output = ctx
+                .docker
+                .logs(
+                    &container_name,
+                    Some(LogsOptionsBuilder::new().stdout(true).stderr(true).build()),
+                )
+                .map_ok(|l| l.to_string())
+                .try_collect::<Vec<_>>()
+                .await?
+                .concat();
+
+            Ok(SolveResult {
+                passed: output.contains(flag),
+                exit_code,
+                output,
+            })
+        }
+        .await;
+
+        remove().await.ok();
+        res.map(Some)
+    }
+}
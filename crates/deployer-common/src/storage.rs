@@ -0,0 +1,370 @@
+use eyre::{Context, Result, eyre};
+use google_cloud_storage::{
+    client::Client as GcsClient,
+    http::objects::{
+        Object,
+        get::GetObjectRequest,
+        upload::{UploadObjectRequest, UploadType},
+    },
+    sign::SignedURLOptions,
+};
+use log::warn;
+use reqwest::{
+    StatusCode, Url,
+    header::{HeaderMap, HeaderName, HeaderValue},
+};
+use s3::{Bucket, Region, creds::Credentials};
+use serde::Deserialize;
+use std::{collections::HashMap, env, future::Future, time::Duration};
+
+/// Custom metadata key holding the sha256 of an uploaded attachment.
+const SHA256_METADATA_KEY: &str = "sha256";
+// the longest gcs and s3 allow signed urls to be valid for
+const MAX_SIGNED_URL_EXPIRY: Duration = Duration::from_secs(604800);
+
+/// Somewhere attachments can be uploaded to and downloaded from.
+pub trait AttachmentStorage {
+    /// sha256 of the object stored under `key`, if there is one.
+    fn stored_sha256(&self, key: &str) -> impl Future<Output = Result<Option<String>>> + Send;
+
+    fn upload(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        sha256: &str,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Link that players download the object from.
+    fn download_url(&self, key: &str) -> impl Future<Output = Result<String>> + Send;
+}
+
+fn join_key(base: &str, key: &str) -> Result<String> {
+    let mut url = Url::parse(base).with_context(|| format!("Invalid url {base}"))?;
+    url.path_segments_mut()
+        .map_err(|_| eyre!("Invalid url {base}"))?
+        .pop_if_empty()
+        .extend(key.split('/'));
+    Ok(url.to_string())
+}
+
+/// Google Cloud Storage. Unless the bucket is public, links point at the platform api, which
+/// redirects to a freshly signed url on every download.
+pub struct GcsStorage {
+    pub client: GcsClient,
+    pub bucket: String,
+    pub public: bool,
+    // platform base whose api re-signs links, without it links are signed here and expire
+    pub redirect_base: Option<String>,
+}
+
+impl GcsStorage {
+    /// Link that works for a week, the longest gcs allows.
+    pub async fn signed_url(&self, key: &str) -> Result<String> {
+        Ok(self
+            .client
+            .signed_url(
+                &self.bucket,
+                key,
+                None,
+                None,
+                SignedURLOptions {
+                    expires: MAX_SIGNED_URL_EXPIRY,
+                    ..Default::default()
+                },
+            )
+            .await?)
+    }
+}
+
+impl AttachmentStorage for GcsStorage {
+    async fn stored_sha256(&self, key: &str) -> Result<Option<String>> {
+        let existing = self
+            .client
+            .get_object(&GetObjectRequest {
+                bucket: self.bucket.clone(),
+                object: key.to_owned(),
+                ..Default::default()
+            })
+            .await
+            .ok();
+
+        Ok(existing
+            .and_then(|o| o.metadata)
+            .and_then(|mut m| m.remove(SHA256_METADATA_KEY)))
+    }
+
+    async fn upload(&self, key: &str, data: Vec<u8>, sha256: &str) -> Result<()> {
+        let upload_type = UploadType::Multipart(Box::new(Object {
+            name: key.to_owned(),
+            metadata: Some(HashMap::from([(
+                SHA256_METADATA_KEY.to_owned(),
+                sha256.to_owned(),
+            )])),
+            ..Default::default()
+        }));
+
+        self.client
+            .upload_object(
+                &UploadObjectRequest {
+                    bucket: self.bucket.clone(),
+                    ..Default::default()
+                },
+                data,
+                &upload_type,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn download_url(&self, key: &str) -> Result<String> {
+        if self.public {
+            return join_key(
+                &format!("https://storage.googleapis.com/{}", self.bucket),
+                key,
+            );
+        }
+
+        if let Some(base) = &self.redirect_base {
+            return join_key(&format!("{base}/api/attachments"), key);
+        }
+
+        warn!(
+            "signed url for {key} expires in 7 days, refresh it with platform update --refresh-attachments"
+        );
+        self.signed_url(key).await
+    }
+}
+
+/// Any S3-compatible object storage (AWS, R2, MinIO, ...). Unless a public url is given, links
+/// point at the platform api, which redirects to a freshly presigned url on every download.
+pub struct S3Storage {
+    pub bucket: Box<Bucket>,
+    // e.g. https://attachments.example.com, objects are expected at <public_url>/<key>
+    pub public_url: Option<String>,
+    // same as for gcs
+    pub redirect_base: Option<String>,
+}
+
+impl S3Storage {
+    pub fn new(
+        bucket: &str,
+        region: String,
+        endpoint: Option<String>,
+        access_key: Option<&str>,
+        secret_key: Option<&str>,
+        public_url: Option<String>,
+    ) -> Result<Self> {
+        let region = match endpoint {
+            Some(endpoint) => Region::Custom { region, endpoint },
+            None => region.parse()?,
+        };
+        let credentials = Credentials::new(access_key, secret_key, None, None, None)?;
+        // path style so it works with minio and friends
+        let bucket = Bucket::new(bucket, region, credentials)?.with_path_style();
+
+        Ok(Self {
+            bucket,
+            public_url,
+            redirect_base: None,
+        })
+    }
+
+    /// Link that works for a week, the longest s3 allows.
+    pub async fn signed_url(&self, key: &str) -> Result<String> {
+        Ok(self
+            .bucket
+            .presign_get(key, MAX_SIGNED_URL_EXPIRY.as_secs() as u32, None)
+            .await?)
+    }
+}
+
+impl AttachmentStorage for S3Storage {
+    async fn stored_sha256(&self, key: &str) -> Result<Option<String>> {
+        Ok(match self.bucket.head_object(key).await {
+            Ok((head, 200)) => head
+                .metadata
+                .and_then(|mut m| m.remove(SHA256_METADATA_KEY)),
+            _ => None,
+        })
+    }
+
+    async fn upload(&self, key: &str, data: Vec<u8>, sha256: &str) -> Result<()> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            format!("x-amz-meta-{SHA256_METADATA_KEY}").parse::<HeaderName>()?,
+            HeaderValue::from_str(sha256)?,
+        );
+
+        let res = self
+            .bucket
+            .put_object_with_content_type_and_headers(
+                key,
+                &data,
+                "application/octet-stream",
+                Some(headers),
+            )
+            .await?;
+        if res.status_code() != 200 {
+            return Err(eyre!(
+                "Failed to upload {key} to S3 ({}): {}",
+                res.status_code(),
+                String::from_utf8_lossy(res.as_slice())
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn download_url(&self, key: &str) -> Result<String> {
+        if let Some(public_url) = &self.public_url {
+            return join_key(public_url, key);
+        }
+
+        if let Some(base) = &self.redirect_base {
+            return join_key(&format!("{base}/api/attachments"), key);
+        }
+
+        warn!(
+            "presigned url for {key} expires in 7 days, refresh it with platform update --refresh-attachments"
+        );
+        self.signed_url(key).await
+    }
+}
+
+/// Uploads attachments to the platform api, which stores them on its local disk and serves them
+/// itself. Links never expire.
+pub struct ApiStorage {
+    // should be authenticated as admin
+    pub client: reqwest::Client,
+    pub platform_base: String,
+}
+
+#[derive(Deserialize)]
+struct StoredAttachment {
+    sha256: String,
+}
+
+impl AttachmentStorage for ApiStorage {
+    async fn stored_sha256(&self, key: &str) -> Result<Option<String>> {
+        let res = self
+            .client
+            .get(join_key(
+                &format!("{}/api/admin/attachments", self.platform_base),
+                key,
+            )?)
+            .send()
+            .await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            res.error_for_status()?
+                .json::<StoredAttachment>()
+                .await?
+                .sha256,
+        ))
+    }
+
+    async fn upload(&self, key: &str, data: Vec<u8>, _sha256: &str) -> Result<()> {
+        self.client
+            .put(join_key(
+                &format!("{}/api/admin/attachments", self.platform_base),
+                key,
+            )?)
+            .body(data)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    async fn download_url(&self, key: &str) -> Result<String> {
+        join_key(&format!("{}/api/attachments", self.platform_base), key)
+    }
+}
+
+/// Storage backend picked at runtime.
+pub enum Storage {
+    Gcs(Box<GcsStorage>),
+    S3(S3Storage),
+    Api(ApiStorage),
+}
+
+impl Storage {
+    /// Gcs (`kind` "gcs") or s3 ("s3") storage from the `GCS_*`/`S3_*` env vars, `None` for any
+    /// other kind. The cli and the api read the same vars, so they agree on where things are.
+    pub async fn remote_from_env(kind: &str) -> Result<Option<Self>> {
+        let flag = |name: &str| env::var(name).is_ok_and(|v| v == "1" || v == "true");
+
+        Ok(Some(match kind {
+            "gcs" => Self::Gcs(Box::new(GcsStorage {
+                client: GcsClient::new(
+                    google_cloud_storage::client::ClientConfig::default()
+                        .with_auth()
+                        .await?,
+                ),
+                bucket: env::var("GCS_ATTACHMENTS_BUCKET")
+                    .context("GCS_ATTACHMENTS_BUCKET is not set")?,
+                public: flag("GCS_ATTACHMENTS_PUBLIC"),
+                redirect_base: None,
+            })),
+            "s3" => Self::S3(S3Storage::new(
+                &env::var("S3_ATTACHMENTS_BUCKET").context("S3_ATTACHMENTS_BUCKET is not set")?,
+                env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_owned()),
+                env::var("S3_ENDPOINT").ok(),
+                env::var("S3_ACCESS_KEY_ID").ok().as_deref(),
+                env::var("S3_SECRET_ACCESS_KEY").ok().as_deref(),
+                env::var("S3_PUBLIC_URL").ok(),
+            )?),
+            _ => return Ok(None),
+        }))
+    }
+
+    /// Has downloads go through the platform at `platform_base` instead of handing out links
+    /// that expire. Does nothing for public buckets and the api backend.
+    pub fn redirect_through(&mut self, platform_base: &str) {
+        match self {
+            Self::Gcs(s) => s.redirect_base = Some(platform_base.to_owned()),
+            Self::S3(s) => s.redirect_base = Some(platform_base.to_owned()),
+            Self::Api(_) => {}
+        }
+    }
+
+    /// Freshly signed link for `key`, `None` for the api backend which serves files itself.
+    pub async fn signed_url(&self, key: &str) -> Result<Option<String>> {
+        match self {
+            Self::Gcs(s) => s.signed_url(key).await.map(Some),
+            Self::S3(s) => s.signed_url(key).await.map(Some),
+            Self::Api(_) => Ok(None),
+        }
+    }
+}
+
+impl AttachmentStorage for Storage {
+    async fn stored_sha256(&self, key: &str) -> Result<Option<String>> {
+        match self {
+            Self::Gcs(s) => s.stored_sha256(key).await,
+            Self::S3(s) => s.stored_sha256(key).await,
+            Self::Api(s) => s.stored_sha256(key).await,
+        }
+    }
+
+    async fn upload(&self, key: &str, data: Vec<u8>, sha256: &str) -> Result<()> {
+        match self {
+            Self::Gcs(s) => s.upload(key, data, sha256).await,
+            Self::S3(s) => s.upload(key, data, sha256).await,
+            Self::Api(s) => s.upload(key, data, sha256).await,
+        }
+    }
+
+    async fn download_url(&self, key: &str) -> Result<String> {
+        match self {
+            Self::Gcs(s) => s.download_url(key).await,
+            Self::S3(s) => s.download_url(key).await,
+            Self::Api(s) => s.download_url(key).await,
+        }
+    }
+}
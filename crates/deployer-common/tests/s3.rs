@@ -0,0 +1,67 @@
+//! Runs the s3 backend against a real s3-compatible server, e.g.
+//!
+//! ```sh
+//! docker run -d -p 9000:9000 minio/minio server /data
+//! docker run --rm --network host --entrypoint sh minio/mc -c \
+//!     'mc alias set local http://localhost:9000 minioadmin minioadmin && mc mb local/attachments'
+//! S3_TEST_ENDPOINT=http://localhost:9000 S3_TEST_BUCKET=attachments \
+//!     S3_TEST_ACCESS_KEY_ID=minioadmin S3_TEST_SECRET_ACCESS_KEY=minioadmin \
+//!     cargo test -p nerine-deployer-common --test s3
+//! ```
+//!
+//! Skipped unless `S3_TEST_ENDPOINT` is set.
+
+use std::env;
+
+use nerine_deployer_common::storage::{AttachmentStorage, S3Storage, Storage};
+
+fn storage() -> Option<S3Storage> {
+    let endpoint = env::var("S3_TEST_ENDPOINT").ok()?;
+    Some(
+        S3Storage::new(
+            &env::var("S3_TEST_BUCKET").expect("S3_TEST_BUCKET is not set"),
+            "us-east-1".to_owned(),
+            Some(endpoint),
+            env::var("S3_TEST_ACCESS_KEY_ID").ok().as_deref(),
+            env::var("S3_TEST_SECRET_ACCESS_KEY").ok().as_deref(),
+            None,
+        )
+        .unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn upload_and_download() {
+    let Some(storage) = storage() else {
+        eprintln!("S3_TEST_ENDPOINT is not set, skipping");
+        return;
+    };
+    let key = format!("nerine-test/{}/flag.txt", std::process::id());
+    let data = b"flag{s3}".to_vec();
+
+    storage.upload(&key, data.clone(), "abc123").await.unwrap();
+    assert_eq!(
+        storage.stored_sha256(&key).await.unwrap().as_deref(),
+        Some("abc123")
+    );
+    assert_eq!(
+        storage.stored_sha256("nerine-test/missing").await.unwrap(),
+        None
+    );
+
+    let url = storage.download_url(&key).await.unwrap();
+    let res = reqwest::get(&url).await.unwrap();
+    assert!(res.status().is_success(), "{url}: {}", res.status());
+    assert_eq!(res.bytes().await.unwrap().as_ref(), data.as_slice());
+
+    // links handed to players go through the platform, which re-signs them
+    let mut storage = Storage::S3(storage);
+    storage.redirect_through("https://ctf.example.com");
+    let Storage::S3(storage) = &storage else {
+        unreachable!()
+    };
+    assert_eq!(
+        storage.download_url(&key).await.unwrap(),
+        format!("https://ctf.example.com/api/attachments/{key}")
+    );
+}
@@ -2,12 +2,12 @@ use std::collections::HashMap;
 
 use axum::{
     Json, Router,
-    extract::{Path, State as StateE},
+    extract::{Path, Query, State as StateE},
     routing::{get, post},
 };
 use chrono::NaiveDateTime;
 use deployer_common::challenge::Challenge;
-use log::debug;
+use log::{debug, info};
 use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
 use sqlx::types::JsonValue;
@@ -16,6 +16,7 @@ use crate::{
     Result, State,
     config::write_challenges_to_dir,
     deploy::{self, ChallengeDeployment},
+    verify::{self, SolveCheck},
 };
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -142,6 +143,24 @@ async fn get_challenge(
     Ok(Json(deployment.sanitize()))
 }
 
+#[derive(Deserialize)]
+struct ChallengeVerifyReq {
+    challenge_id: i32,
+}
+
+async fn verify_challenge(
+    StateE(state): StateE<State>,
+    Json(payload): Json<ChallengeVerifyReq>,
+) -> Result<Json<SolveCheck>> {
+    let check = verify::create_check(&state, payload.challenge_id, false).await?;
+
+    state
+        .tasks
+        .spawn(verify::verify_fresh_task(state.clone(), check.clone()));
+
+    Ok(Json(check))
+}
+
 async fn reload_challenges(StateE(state): StateE<State>) -> Result<()> {
     debug!("Reloading challenges");
     let mut challs_new = crate::config::load_challenges_from_dir(&state.config.challenges_dir)?;
@@ -154,11 +173,54 @@ async fn reload_challenges(StateE(state): StateE<State>) -> Result<()> {
     Ok(())
 }
 
+#[derive(Deserialize)]
+struct LoadQuery {
+    #[serde(default)]
+    prune: bool,
+}
+
+/// Destroys every live deployment of challenges that aren't in `challs`. Runs before the new
+/// challenges are loaded, since destroying needs the old challenge data.
+async fn destroy_removed(state: &State, challs: &HashMap<String, Challenge>) -> Result<()> {
+    let removed: Vec<String> = {
+        let rg = state.challenge_data.read().await;
+        rg.keys()
+            .filter(|id| !challs.contains_key(*id))
+            .cloned()
+            .collect()
+    };
+
+    let deployments = sqlx::query_as!(
+        ChallengeDeploymentRow,
+        "SELECT d.* FROM challenge_deployments d JOIN challenges c ON c.id = d.challenge_id
+        WHERE c.public_id = ANY($1) AND d.destroyed_at IS NULL",
+        &removed,
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    for deployment in deployments {
+        info!(
+            "Destroying deployment {} of removed challenge {}",
+            deployment.public_id, deployment.challenge_id
+        );
+        let mut tx = state.db.begin().await?;
+        deploy::destroy_challenge(state.clone(), &mut tx, deployment.try_into()?).await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
 async fn load_challenges(
     StateE(state): StateE<State>,
+    Query(query): Query<LoadQuery>,
     Json(challs): Json<HashMap<String, Challenge>>,
 ) -> Result<()> {
     debug!("Loading challenges from api endpoint");
+    if query.prune {
+        destroy_removed(&state, &challs).await?;
+    }
     let mut wg = state.challenge_data.write().await;
     // FIXME: use a staging dir so that old challs are kept if loading fails
     write_challenges_to_dir(&state.config.challenges_dir, challs)?;
@@ -175,5 +237,6 @@ pub fn router() -> Router<crate::State> {
         .route("/challenges/load", post(load_challenges))
         .route("/challenge/deploy", post(deploy_challenge))
         .route("/challenge/destroy", post(destroy_challenge))
+        .route("/challenge/verify", post(verify_challenge))
         .route("/deployment/{id}", get(get_challenge))
 }
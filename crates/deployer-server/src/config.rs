@@ -8,8 +8,9 @@ use std::{
     sync::Arc,
 };
 
-use deployer_common::challenge::{
-    Challenge, DeployableContextData, DeploymentStrategy, ExposeType,
+use deployer_common::{
+    challenge::{Challenge, DeployableContextData, DeploymentStrategy, ExposeType},
+    deploy::calculate_static_tcp_port,
 };
 use envconfig::Envconfig;
 use eyre::eyre;
@@ -19,8 +20,6 @@ use sqlx::PgPool;
 use tokio::sync::RwLock;
 use tokio_util::task::TaskTracker;
 
-use crate::deploy::calculate_static_tcp_port;
-
 // god-awful keychain-type thing
 #[derive(Debug, Clone, Deserialize)]
 pub struct HostKeychain {
@@ -129,6 +128,9 @@ pub struct Config {
     pub host_keychains: HostKeychainEnv,
     #[envconfig(from = "CHALLENGES_DIR")]
     pub challenges_dir: PathBuf,
+    // seconds between solve checks of static deployments, unset to disable
+    #[envconfig(from = "SOLVE_CHECK_INTERVAL")]
+    pub solve_check_interval: Option<u64>,
 }
 
 pub fn load_challenges_from_dir(dir: &Path) -> eyre::Result<HashMap<String, Challenge>> {
@@ -5,13 +5,15 @@ use bollard::{
         CreateContainerOptionsBuilder, InspectContainerOptions, RemoveContainerOptionsBuilder,
         StartContainerOptions,
     },
-    secret::{
-        ContainerCreateBody, EndpointSettings, HostConfig, NetworkCreateRequest, NetworkingConfig,
-        PortBinding,
-    },
+    secret::NetworkCreateRequest,
 };
 use chrono::NaiveDateTime;
-use deployer_common::challenge::{Container, DeployableContext, DeploymentStrategy, ExposeType};
+use deployer_common::{
+    challenge::{Container, DeployableContext, DeploymentStrategy, ExposeType},
+    deploy::{
+        calculate_container_name, calculate_network_name, calculate_static_tcp_port, container_body,
+    },
+};
 use eyre::eyre;
 use log::{debug, error};
 use serde::{Deserialize, Serialize};
@@ -76,31 +78,6 @@ pub enum HostMapping {
     Http { subdomain: String, base: String },
 }
 
-fn calculate_container_name(
-    chall_id: &str,
-    strategy: DeploymentStrategy,
-    ct: &str,
-    team_id: Option<i32>,
-) -> String {
-    match strategy {
-        DeploymentStrategy::Static => format!("{}-container-{}", chall_id, ct),
-        DeploymentStrategy::Instanced => {
-            format!("{}-team-{}-container-{}", chall_id, team_id.unwrap(), ct)
-        }
-    }
-}
-
-fn calculate_network_name(
-    chall_id: &str,
-    strategy: DeploymentStrategy,
-    team_id: Option<i32>,
-) -> String {
-    match strategy {
-        DeploymentStrategy::Static => format!("{}-network", chall_id),
-        DeploymentStrategy::Instanced => format!("{}-team-{}-network", chall_id, team_id.unwrap()),
-    }
-}
-
 fn get_unused_port() -> u16 {
     loop {
         if let Ok(l) = std::net::TcpListener::bind(("0.0.0.0", 0)) {
@@ -131,25 +108,6 @@ fn calculate_subdomain(chall_id: &str, pub_team_id: Option<&str>, port: u16) ->
     format!("{}-{}", chall_id, end)
 }
 
-pub(crate) fn calculate_static_tcp_port(
-    chall_id: &str,
-    container_name: &str,
-    port: u16,
-    bump: u64,
-) -> u16 {
-    let h = {
-        use sha2::Digest;
-        use std::io::Write;
-
-        let mut hasher = sha2::Sha256::new();
-        write!(hasher, "{}/{}/{},{}", chall_id, container_name, port, bump,).unwrap();
-        hasher.finalize()
-    };
-    // take first 16 bits
-    let n = u16::from_le_bytes(h[..2].try_into().unwrap());
-    n.saturating_add(1025)
-}
-
 #[derive(Debug, Clone)]
 struct DockerGuard {
     ctx: Arc<DeployableContext>,
@@ -414,6 +372,13 @@ pub async fn deploy_challenge(
             )
             .await
             .ok();
+        let tcp_ports = mappings
+            .iter()
+            .filter_map(|(k, v)| match v {
+                HostMapping::Tcp { port: p, .. } => Some((*k, *p)),
+                _ => None,
+            })
+            .collect();
         ctx.docker
             .create_container(
                 Some(
@@ -421,61 +386,7 @@ pub async fn deploy_challenge(
                         .name(&container_name)
                         .build(),
                 ),
-                ContainerCreateBody {
-                    env: chall_container.env.as_ref().map(|h| {
-                        h.iter()
-                            .map(|(k, v)| format!("{}={}", k, v))
-                            .collect::<Vec<_>>()
-                    }),
-                    /* todo: resource limits */
-                    image: Some(chall_data.image_id(&ctx, ct)),
-                    networking_config: Some(NetworkingConfig {
-                        endpoints_config: Some({
-                            let mut h = HashMap::new();
-                            h.insert(
-                                network_name.clone(),
-                                EndpointSettings {
-                                    aliases: Some(vec![ct.clone()]),
-                                    ..Default::default()
-                                },
-                            );
-                            h
-                        }),
-                    }),
-                    exposed_ports: Some(
-                        mappings
-                            .iter()
-                            .filter(|(_, v)| matches!(v, HostMapping::Tcp { .. }))
-                            .map(|(k, _)| (format!("{}/tcp", k), Default::default()))
-                            .collect::<HashMap<_, _>>(),
-                    ),
-                    host_config: Some(HostConfig {
-                        nano_cpus: chall_container.limits.cpu, // nanocpus (10 ^ -9 cpus)
-                        memory: chall_container.limits.mem,    // bytes
-                        port_bindings: Some(
-                            mappings
-                                .iter()
-                                .filter_map(|(k, v)| match v {
-                                    HostMapping::Tcp { port: p, .. } => Some((*k, *p)),
-                                    _ => None,
-                                })
-                                .map(|(p1, p2)| {
-                                    (
-                                        format!("{}/tcp", p1),
-                                        Some(vec![PortBinding {
-                                            host_ip: Some("0.0.0.0".to_owned()),
-                                            host_port: Some(format!("{}", p2)),
-                                        }]),
-                                    )
-                                })
-                                .collect::<HashMap<_, _>>(),
-                        ),
-                        cap_add: chall_container.cap_add.clone(),
-                        privileged: chall_container.privileged.clone(),
-                        ..Default::default()
-                    }),
-                    ..Default::default()
-                },
+                container_body(&chall_data, &ctx, ct, &network_name, &tcp_ports),
             )
             .await?;
         _docker_guard.container(&container_name);
@@ -7,6 +7,7 @@ mod api;
 mod config;
 mod deploy;
 mod error;
+mod verify;
 
 use config::State;
 use error::Result;
@@ -72,6 +73,14 @@ async fn main() -> eyre::Result<()> {
         }
     }
 
+    if let Some(interval) = state.config.solve_check_interval {
+        tt.spawn(verify::periodic_verify_task(
+            state.clone(),
+            std::time::Duration::from_secs(interval),
+            ct.clone(),
+        ));
+    }
+
     let app = Router::<State>::new()
         .nest("/api", api::router())
         .with_state(state);
@@ -0,0 +1,259 @@
+use std::{collections::HashMap, time::Duration};
+
+use bollard::{
+    query_parameters::{
+        CreateContainerOptionsBuilder, RemoveContainerOptionsBuilder, StartContainerOptions,
+    },
+    secret::NetworkCreateRequest,
+};
+use chrono::NaiveDateTime;
+use deployer_common::{
+    challenge::{Challenge, DeployableContext, DeploymentStrategy},
+    deploy::{calculate_network_name, container_body},
+    solve::SolveResult,
+};
+use eyre::eyre;
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+use crate::{Result, State};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SolveCheck {
+    pub id: i32,
+    pub challenge_id: i32,
+    pub periodic: bool,
+    pub passed: Option<bool>,
+    pub exit_code: Option<i64>,
+    pub output: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub finished_at: Option<NaiveDateTime>,
+}
+
+// the end of the output is kept, that's usually where the flag or the error is
+const MAX_OUTPUT_LEN: usize = 64 * 1024;
+
+fn truncate_output(output: &str) -> &str {
+    let mut start = output.len().saturating_sub(MAX_OUTPUT_LEN);
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    &output[start..]
+}
+
+// separate from the real deployment and from other checks so they can all run at once
+fn verify_network_name(chall_id: &str, check_id: i32) -> String {
+    format!("{}-verify-{}-network", chall_id, check_id)
+}
+
+fn verify_container_name(chall_id: &str, check_id: i32, ct: &str) -> String {
+    format!("{}-verify-{}-container-{}", chall_id, check_id, ct)
+}
+
+pub async fn create_check(state: &State, challenge_id: i32, periodic: bool) -> Result<SolveCheck> {
+    Ok(sqlx::query_as!(
+        SolveCheck,
+        "INSERT INTO solve_checks (challenge_id, periodic) VALUES ($1, $2) RETURNING *",
+        challenge_id,
+        periodic,
+    )
+    .fetch_one(&state.db)
+    .await?)
+}
+
+async fn finish_check(state: &State, check: &SolveCheck, res: eyre::Result<SolveResult>) {
+    let (passed, exit_code, output) = match res {
+        Ok(res) => (res.passed, res.exit_code, res.output),
+        Err(e) => (false, None, format!("{e:?}")),
+    };
+    if !passed {
+        warn!(
+            "solve check {} for challenge {} failed",
+            check.id, check.challenge_id
+        );
+    }
+
+    if let Err(e) = sqlx::query!(
+        "UPDATE solve_checks SET passed = $2, exit_code = $3, output = $4, finished_at = NOW() WHERE id = $1",
+        check.id,
+        passed,
+        exit_code,
+        truncate_output(&output),
+    )
+    .execute(&state.db)
+    .await
+    {
+        error!("Failed to record solve check {}: {:?}", check.id, e);
+    }
+}
+
+/// Challenge data, flag and docker context for a challenge db id.
+async fn challenge_for(
+    state: &State,
+    challenge_id: i32,
+) -> eyre::Result<(Challenge, String, DeployableContext)> {
+    let row = sqlx::query!(
+        "SELECT public_id, flag FROM challenges WHERE id = $1",
+        challenge_id
+    )
+    .fetch_one(&state.db)
+    .await?;
+
+    let chall_data = {
+        let rg = state.challenge_data.read().await;
+        rg.get(&row.public_id).cloned()
+    }
+    .ok_or_else(|| eyre!("failed to get challenge data for {}", row.public_id))?;
+    if chall_data.solve.is_none() {
+        return Err(eyre!("challenge {} does not have a solve", chall_data.id));
+    }
+
+    let host = chall_data.host.as_deref().unwrap_or("default");
+    let host_keychain = state
+        .config
+        .host_keychains
+        .get(host)
+        .ok_or_else(|| eyre!("challenge {} uses unknown host {host}", chall_data.id))?;
+    let ctx: DeployableContext = host_keychain.docker.clone().try_into()?;
+
+    Ok((chall_data, row.flag, ctx))
+}
+
+async fn remove_container(ctx: &DeployableContext, name: &str) {
+    ctx.docker
+        .remove_container(
+            name,
+            Some(
+                RemoveContainerOptionsBuilder::new()
+                    .v(true)
+                    .force(true)
+                    .build(),
+            ),
+        )
+        .await
+        .ok();
+}
+
+/// Starts a throwaway copy of the challenge without exposed ports, and runs the solver on it.
+async fn verify_fresh(state: &State, check: &SolveCheck) -> eyre::Result<SolveResult> {
+    let (chall_data, flag, ctx) = challenge_for(state, check.challenge_id).await?;
+    let Some(chall_containers) = &chall_data.container else {
+        return Err(eyre!("challenge {} does not have container", chall_data.id));
+    };
+
+    chall_data.pull(&ctx).await?;
+    chall_data.pull_solve(&ctx).await?;
+
+    let network_name = verify_network_name(&chall_data.id, check.id);
+    ctx.docker
+        .create_network(NetworkCreateRequest {
+            name: network_name.clone(),
+            ..Default::default()
+        })
+        .await?;
+
+    let mut containers = vec![];
+    let res = async {
+        for ct in chall_containers.keys() {
+            let container_name = verify_container_name(&chall_data.id, check.id, ct);
+            ctx.docker
+                .create_container(
+                    Some(
+                        CreateContainerOptionsBuilder::new()
+                            .name(&container_name)
+                            .build(),
+                    ),
+                    container_body(&chall_data, &ctx, ct, &network_name, &HashMap::new()),
+                )
+                .await?;
+            containers.push(container_name.clone());
+            ctx.docker
+                .start_container(&container_name, None::<StartContainerOptions>)
+                .await?;
+        }
+
+        debug!("started verify deployment for {}", chall_data.id);
+        chall_data
+            .run_solve(&ctx, &network_name, &flag)
+            .await?
+            .ok_or_else(|| eyre!("challenge {} does not have a solve", chall_data.id))
+    }
+    .await;
+
+    for container in &containers {
+        remove_container(&ctx, container).await;
+    }
+    ctx.docker.remove_network(&network_name).await.ok();
+
+    res
+}
+
+/// Runs the solver against the live deployment of a static challenge.
+async fn verify_live(state: &State, challenge_id: i32) -> eyre::Result<SolveResult> {
+    let (chall_data, flag, ctx) = challenge_for(state, challenge_id).await?;
+    chall_data.pull_solve(&ctx).await?;
+
+    let network_name = calculate_network_name(&chall_data.id, DeploymentStrategy::Static, None);
+    chall_data
+        .run_solve(&ctx, &network_name, &flag)
+        .await?
+        .ok_or_else(|| eyre!("challenge {} does not have a solve", chall_data.id))
+}
+
+pub async fn verify_fresh_task(state: State, check: SolveCheck) {
+    let res = verify_fresh(&state, &check).await;
+    finish_check(&state, &check, res).await;
+}
+
+/// Every `interval`, checks each deployed static challenge that has a solve, until `ct` is
+/// cancelled.
+pub async fn periodic_verify_task(state: State, interval: Duration, ct: CancellationToken) {
+    loop {
+        if ct
+            .run_until_cancelled(tokio::time::sleep(interval))
+            .await
+            .is_none()
+        {
+            return;
+        }
+
+        let deployed = match sqlx::query!(
+            "SELECT c.id, c.public_id FROM challenge_deployments d JOIN challenges c ON c.id = d.challenge_id
+            WHERE d.team_id IS NULL AND d.deployed AND d.destroyed_at IS NULL"
+        )
+        .fetch_all(&state.db)
+        .await
+        {
+            Ok(d) => d,
+            Err(e) => {
+                error!("Failed to list static deployments to verify: {:?}", e);
+                continue;
+            }
+        };
+
+        for chall in deployed {
+            if ct.is_cancelled() {
+                return;
+            }
+            let has_solve = {
+                let rg = state.challenge_data.read().await;
+                rg.get(&chall.public_id).is_some_and(|c| c.solve.is_some())
+            };
+            if !has_solve {
+                continue;
+            }
+
+            info!("verifying {}", chall.public_id);
+            let check = match create_check(&state, chall.id, true).await {
+                Ok(c) => c,
+                Err(e) => {
+                    error!("Failed to create solve check: {:?}", e);
+                    continue;
+                }
+            };
+            let res = verify_live(&state, chall.id).await;
+            finish_check(&state, &check, res).await;
+        }
+    }
+}
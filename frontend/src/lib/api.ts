@@ -24,6 +24,11 @@ export interface GenericApiError {
     | "event_ended"
     | "wrong_flag"
     | "team_name_taken"
+    | "email_taken"
+    | "not_in_team"
+    | "already_in_team"
+    | "not_captain"
+    | "team_full"
     | "generic_error";
   message: string;
 }
@@ -92,7 +97,8 @@ export const register = async (
 };
 
 interface TeamId {
-  id: string;
+  // null for users that aren't in a team yet
+  id: string | null;
 }
 
 export const login = async (token: string): Promise<TeamId | ApiError> => {
@@ -124,9 +130,16 @@ export interface VerificationDetailsEmailUpdate {
   new_email: string;
 }
 
+export interface VerificationDetailsUserRegistration {
+  type: "user_registration";
+  name: string;
+  email: string;
+}
+
 export type VerificationDetails =
   | VerificationDetailsTeamRegistration
-  | VerificationDetailsEmailUpdate;
+  | VerificationDetailsEmailUpdate
+  | VerificationDetailsUserRegistration;
 
 export const getVerificationDetails = async (
   token: string
@@ -153,6 +166,8 @@ interface Solve {
   name: string;
   category: string;
   points: number;
+  // blood bonus
+  bonus: number;
   solved_at: string;
 }
 
@@ -201,6 +216,14 @@ export const genToken = async (): Promise<Token | ApiError> => {
   return (await res.json()) as Token | ApiError;
 };
 
+export interface Attachment {
+  url: string;
+  // null for external links
+  sha256: string | null;
+  size: number | null;
+  display_name: string | null;
+}
+
 export interface Challenge {
   id: string;
   name: string;
@@ -208,10 +231,14 @@ export interface Challenge {
   description: string;
   points: number;
   solves: number;
-  attachments: { [name: string]: string };
+  attachments: { [name: string]: Attachment };
   strategy: "static" | "instanced";
   deployment_id: string | null;
   category: string;
+  // parts of a series share a group
+  group: string | null;
+  tags: string[];
+  difficulty: string | null;
   solved_at: Date | null;
 }
 
@@ -224,6 +251,22 @@ export const challenges = async (
 
   return challs.map((c) => ({
     ...c,
+    // older challenges only have the url
+    attachments: Object.fromEntries(
+      Object.entries(
+        (c.attachments ?? {}) as { [name: string]: Attachment | string }
+      ).map(([name, a]) => [
+        name,
+        typeof a === "string"
+          ? { url: a, sha256: null, size: null, display_name: null }
+          : {
+              url: a.url,
+              sha256: a.sha256 ?? null,
+              size: a.size ?? null,
+              display_name: a.display_name ?? null,
+            },
+      ])
+    ),
     solved_at: c.solved_at && new Date(c.solved_at + "Z"),
   }));
 };
@@ -232,6 +275,8 @@ export interface ChallengeSolve {
   id: string;
   name: string;
   solved_at: Date;
+  // blood bonus
+  bonus: number;
 }
 
 export const challengeSolves = async (
@@ -260,19 +305,82 @@ export interface LeaderboardEntry {
   id: string;
   name: string;
   score: number;
+  rank: number;
+  solves: number;
+  last_solve: string | null;
+  // only the top teams have a history
   score_history: ScorePoint[];
   extra: { badges: Badge[] };
 }
-export const leaderboard = async (division?: string | null): Promise<LeaderboardEntry[] | ApiError> => {
-  const res = await req("GET", `/leaderboard${division ? "/" + division : ""}`);
-  return (await res.json()) as LeaderboardEntry[] | ApiError;
+
+export interface LeaderboardPage {
+  // teams in the division matching the search, not just the ones on this page
+  total: number;
+  teams: LeaderboardEntry[];
+  next_cursor: number | null;
+}
+
+export interface LeaderboardOptions {
+  cursor?: number;
+  limit?: number;
+  search?: string;
+  sort?: "score" | "solves" | "badges" | "last_solve";
+  // the teams around your own instead, needs to be logged in
+  aroundMe?: boolean;
+  // on top of the division
+  divisions?: string[];
+  // only counts solves in the category, teams have no score history then
+  category?: string;
+}
+
+export const leaderboard = async (
+  division?: string | null,
+  opts: LeaderboardOptions = {},
+): Promise<LeaderboardPage | ApiError> => {
+  const params = new URLSearchParams();
+  if (opts.cursor !== undefined) params.set("cursor", opts.cursor.toString());
+  if (opts.limit !== undefined) params.set("limit", opts.limit.toString());
+  if (opts.search) params.set("search", opts.search);
+  if (opts.sort) params.set("sort", opts.sort);
+  if (opts.aroundMe) params.set("around_me", "true");
+  if (opts.divisions?.length) params.set("divisions", opts.divisions.join(","));
+  if (opts.category) params.set("category", opts.category);
+  const res = await req("GET", `/leaderboard${division ? "/" + division : ""}?${params}`);
+  return (await res.json()) as LeaderboardPage | ApiError;
+};
+
+export interface TeamHistory {
+  id: string;
+  name: string;
+  score_history: ScorePoint[];
+}
+
+// history of the given teams and of the top `top` teams, at most 50 in total
+export const scoreHistory = async (opts: {
+  teams?: string[];
+  top?: number;
+  divisions?: string[];
+}): Promise<TeamHistory[] | ApiError> => {
+  const params = new URLSearchParams();
+  if (opts.teams?.length) params.set("teams", opts.teams.join(","));
+  if (opts.top) params.set("top", opts.top.toString());
+  if (opts.divisions?.length) params.set("divisions", opts.divisions.join(","));
+  const res = await req("GET", `/leaderboard/history?${params}`);
+  return (await res.json()) as TeamHistory[] | ApiError;
 };
 
+export interface SubmitResult {
+  // the challenge's points after the solve
+  points: number;
+  // 1 for first blood
+  place: number | null;
+}
+
 export async function submitFlag(
   challengeId: string,
   flag: string,
   token?: string
-): Promise<{} | ApiError> {
+): Promise<SubmitResult | ApiError> {
   const res = await req("POST", "/challs/submit", {
     ...tokenToOptions(token),
     body: {
@@ -280,11 +388,7 @@ export async function submitFlag(
       flag,
     },
   });
-  if (res.status == 200) {
-    return {};
-  } else {
-    return await res.json();
-  }
+  return await res.json();
 }
 
 export interface Event {
@@ -293,6 +397,10 @@ export interface Event {
   start_time: Date;
   end_time: Date;
   divisions: { [id: string] : string }
+  // teams of the listed divisions also appear on the key division's leaderboard
+  division_includes: { [id: string]: string[] }
+  // caps user accounts per team
+  max_team_size: number | null
 }
 
 // TODO(ani): don't assume this always succeeds
@@ -363,3 +471,100 @@ export async function getChallengeDeployment(
 
   return (await res.json()) as ChallengeDeployment | ApiError;
 }
+
+export const registerUser = async (
+  email: string,
+  name: string
+): Promise<{ email: string } | ApiError> => {
+  const res = await req("POST", "/users/register", {
+    body: { email, name },
+  });
+  return (await res.json()) as { email: string } | ApiError;
+};
+
+export const verifyUser = async (token: string): Promise<TeamId | ApiError> => {
+  const res = await req("POST", "/users/verify", {
+    body: { token },
+  });
+  return (await res.json()) as TeamId | ApiError;
+};
+
+export const resendUserToken = async (email: string): Promise<void> => {
+  await req("POST", "/users/resend_token", {
+    body: { email },
+  });
+};
+
+export interface Me {
+  id: string;
+  name: string;
+  email: string;
+  team_id: string | null;
+  team_name: string | null;
+  captain: boolean;
+}
+
+export const me = async (token?: string): Promise<Me | ApiError> => {
+  const res = await req("GET", "/users/me", tokenToOptions(token));
+  return (await res.json()) as Me | ApiError;
+};
+
+export interface Member {
+  id: string;
+  name: string;
+  captain: boolean;
+  // solves submitted by this member
+  solves: number;
+}
+
+export interface TeamMembers {
+  invite_code: string;
+  members: Member[];
+}
+
+export const teamMembers = async (
+  token?: string
+): Promise<TeamMembers | ApiError> => {
+  const res = await req("GET", "/users/team", tokenToOptions(token));
+  return (await res.json()) as TeamMembers | ApiError;
+};
+
+export const createTeam = async (
+  name: string,
+  division?: string | null
+): Promise<TeamId | ApiError> => {
+  const res = await req("POST", "/users/team/create", {
+    body: { name, division: division ?? null },
+  });
+  return (await res.json()) as TeamId | ApiError;
+};
+
+export const joinTeam = async (code: string): Promise<TeamId | ApiError> => {
+  const res = await req("POST", "/users/team/join", {
+    body: { code },
+  });
+  return (await res.json()) as TeamId | ApiError;
+};
+
+// these return an empty body on success
+const teamAction = async (
+  path: string,
+  body: object = {}
+): Promise<ApiError | null> => {
+  const res = await req("POST", `/users/team/${path}`, { body });
+  return res.ok ? null : ((await res.json()) as ApiError);
+};
+
+export const leaveTeam = () => teamAction("leave");
+
+export const kickMember = (user_id: string) => teamAction("kick", { user_id });
+
+export const transferCaptain = (user_id: string) =>
+  teamAction("captain", { user_id });
+
+export const rotateInvite = async (): Promise<
+  { invite_code: string } | ApiError
+> => {
+  const res = await req("POST", "/users/team/invite/rotate", { body: {} });
+  return (await res.json()) as { invite_code: string } | ApiError;
+};
@@ -1,19 +1,28 @@
-import { isError, profile, getEvent } from "$lib/api";
+import { isError, profile, getEvent, me, type Me } from "$lib/api";
 import { jwtDecode } from "jwt-decode";
 
 export const load = async ({ cookies }) => {
   const event = await getEvent();
   const token = cookies.get("token");
-  if (!token) return { authedProfile: null, teamId: null, event };
+  if (!token) return { authedProfile: null, teamId: null, user: null, event };
 
-  const teamId = jwtDecode<{ team_id: string }>(token).team_id;
-  let prof = token ? await profile(teamId, token) : null;
+  const claims = jwtDecode<{ team_id: string; user_id?: string }>(token);
+  let teamId: string | null = claims.team_id;
+  // user tokens don't carry a team, it can change while they're valid
+  let user: Me | null = null;
+  if (claims.user_id) {
+    const res = await me(token);
+    user = isError(res) ? null : res;
+    teamId = user?.team_id ?? null;
+  }
+  let prof = teamId ? await profile(teamId, token) : null;
 
   if (prof != null && isError(prof)) prof = null;
 
   return {
     teamId,
     authedProfile: prof,
+    user,
     event,
   };
 };
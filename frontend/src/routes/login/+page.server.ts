@@ -1,13 +1,22 @@
-import { isError, profile } from "$lib/api";
+import { isError, me, profile } from "$lib/api";
 import { jwtDecode } from "jwt-decode";
 
 export const load = async ({ url }) => {
   const urlToken = url.searchParams.get("token");
   if (urlToken) {
     try {
-      const { team_id } = jwtDecode<{ team_id: string }>(urlToken);
+      const { team_id, user_id } = jwtDecode<{
+        team_id: string;
+        user_id?: string;
+      }>(urlToken);
 
-      if (team_id) {
+      if (user_id) {
+        const user = await me(urlToken);
+
+        if (isError(user)) return { teamName: null, error: user };
+
+        return { teamName: user.name, token: urlToken };
+      } else if (team_id) {
         const prof = await profile(team_id, urlToken);
 
         if (isError(prof)) return { teamName: null, error: prof };
@@ -0,0 +1,16 @@
+import { isError, teamMembers } from "$lib/api";
+import { redirect } from "@sveltejs/kit";
+
+export const load = async ({ cookies, parent, url }) => {
+  const { user, teamId } = await parent();
+  if (!user && !teamId) redirect(307, "/signup");
+
+  const token = cookies.get("token");
+  let team = null;
+  if (teamId) {
+    const res = await teamMembers(token);
+    team = isError(res) ? null : res;
+  }
+
+  return { team, code: url.searchParams.get("code") ?? "" };
+};
@@ -0,0 +1,14 @@
+CREATE TABLE solve_checks (
+    id SERIAL PRIMARY KEY,
+    challenge_id INT NOT NULL REFERENCES challenges(id) ON DELETE CASCADE,
+    -- checks of the live static deployment, rather than a fresh one started on demand
+    periodic BOOLEAN NOT NULL DEFAULT FALSE,
+    -- null while the check is running
+    passed BOOLEAN,
+    exit_code BIGINT,
+    output TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMP
+);
+
+CREATE INDEX solve_checks_challenge_id_idx ON solve_checks (challenge_id, created_at DESC);
@@ -0,0 +1,3 @@
+ALTER TABLE challenges DROP COLUMN difficulty;
+DROP TABLE challenge_tags;
+DROP TABLE tags;
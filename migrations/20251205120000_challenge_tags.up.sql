@@ -0,0 +1,14 @@
+CREATE TABLE tags (
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE challenge_tags (
+    challenge_id INT NOT NULL REFERENCES challenges(id) ON DELETE CASCADE,
+    tag_id INT NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY (challenge_id, tag_id)
+);
+
+CREATE INDEX challenge_tags_tag_id_idx ON challenge_tags (tag_id);
+
+ALTER TABLE challenges ADD COLUMN difficulty TEXT;
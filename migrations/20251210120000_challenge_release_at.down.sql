@@ -0,0 +1,3 @@
+ALTER TABLE challenges
+DROP COLUMN release_at,
+DROP COLUMN release_announced;
@@ -0,0 +1,4 @@
+ALTER TABLE challenges
+ADD COLUMN release_at TIMESTAMP,
+-- set once the release webhook went out, reset when release_at changes
+ADD COLUMN release_announced BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1 @@
+ALTER TABLE challenges DROP COLUMN points_decay;
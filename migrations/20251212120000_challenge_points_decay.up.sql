@@ -0,0 +1,2 @@
+-- overrides the decay from the event's scoring config
+ALTER TABLE challenges ADD COLUMN points_decay DOUBLE PRECISION;
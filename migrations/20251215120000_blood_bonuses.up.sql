@@ -0,0 +1,57 @@
+-- synced from the event's scoring config on startup
+CREATE TABLE blood_bonuses (
+    -- 1 for first blood
+    place INT PRIMARY KEY,
+    points INT NOT NULL DEFAULT 0,
+    -- of the challenge's current points
+    percent INT NOT NULL DEFAULT 0
+);
+
+-- every correct submission with its place among the challenge's solves and the points it's worth
+CREATE FUNCTION scored_solves() RETURNS TABLE (
+    team_id INT,
+    challenge_id INT,
+    created_at TIMESTAMP,
+    place INT,
+    points INT,
+    bonus INT
+) AS $$ WITH solves AS (
+    SELECT team_id,
+        challenge_id,
+        created_at,
+        ROW_NUMBER() OVER (
+            PARTITION BY challenge_id
+            ORDER BY created_at ASC,
+                id ASC
+        )::int AS place
+    FROM submissions
+    WHERE is_correct = true
+)
+SELECT s.team_id,
+    s.challenge_id,
+    s.created_at,
+    s.place,
+    ch.c_points,
+    COALESCE(bb.points + CEIL(ch.c_points * bb.percent / 100.0)::int, 0)
+FROM solves s
+    JOIN challenges ch ON ch.id = s.challenge_id
+    LEFT JOIN blood_bonuses bb ON bb.place = s.place $$ LANGUAGE SQL;
+
+DROP FUNCTION IF EXISTS compute_leaderboard(TEXT);
+CREATE FUNCTION compute_leaderboard(division_filter TEXT DEFAULT NULL) RETURNS TABLE (team_id INT, score INT, rank INT) AS $$ WITH solves AS (
+    SELECT team_id,
+        SUM(points + bonus) AS score,
+        MAX(created_at) AS sub_time
+    FROM scored_solves()
+    GROUP BY team_id
+)
+SELECT t.id,
+    COALESCE(s.score, 0)::int,
+    ROW_NUMBER() OVER (
+        ORDER BY COALESCE(s.score, 0) DESC,
+            s.sub_time ASC NULLS LAST,
+            t.id ASC
+    )::int
+FROM teams t
+    LEFT JOIN solves s ON t.id = s.team_id
+WHERE division_filter IS NULL OR t.division = division_filter $$ LANGUAGE SQL;
@@ -0,0 +1,21 @@
+DROP FUNCTION IF EXISTS compute_leaderboard(TEXT);
+CREATE FUNCTION compute_leaderboard(division_filter TEXT DEFAULT NULL) RETURNS TABLE (team_id INT, score INT, rank INT) AS $$ WITH solves AS (
+    SELECT team_id,
+        SUM(points + bonus) AS score,
+        MAX(created_at) AS sub_time
+    FROM scored_solves()
+    GROUP BY team_id
+)
+SELECT t.id,
+    COALESCE(s.score, 0)::int,
+    ROW_NUMBER() OVER (
+        ORDER BY COALESCE(s.score, 0) DESC,
+            s.sub_time ASC NULLS LAST,
+            t.id ASC
+    )::int
+FROM teams t
+    LEFT JOIN solves s ON t.id = s.team_id
+WHERE division_filter IS NULL OR t.division = division_filter $$ LANGUAGE SQL;
+
+DROP FUNCTION IF EXISTS refresh_team_scores(INT);
+DROP TABLE team_scores;
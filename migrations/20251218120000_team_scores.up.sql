@@ -0,0 +1,46 @@
+-- per team totals behind compute_leaderboard, refreshed whenever a challenge's points change
+CREATE TABLE team_scores (
+    team_id INT PRIMARY KEY REFERENCES teams(id) ON DELETE CASCADE,
+    score INT NOT NULL DEFAULT 0,
+    last_solve TIMESTAMP
+);
+
+-- recomputes the totals of every team that solved the challenge, or of every team if it's NULL.
+-- refreshes run one at a time so concurrent solves by the same team can't overwrite each other
+-- with totals that miss the other solve.
+CREATE FUNCTION refresh_team_scores(chall INT DEFAULT NULL) RETURNS VOID AS $$
+SELECT pg_advisory_xact_lock('team_scores'::regclass::oid::bigint);
+DELETE FROM team_scores
+WHERE chall IS NULL;
+INSERT INTO team_scores (team_id, score, last_solve)
+SELECT team_id,
+    SUM(points + bonus),
+    MAX(created_at)
+FROM scored_solves()
+WHERE chall IS NULL
+    OR team_id IN (
+        SELECT team_id
+        FROM submissions
+        WHERE challenge_id = chall
+            AND is_correct = true
+    )
+GROUP BY team_id ON CONFLICT (team_id) DO
+UPDATE
+SET score = EXCLUDED.score,
+    last_solve = EXCLUDED.last_solve;
+$$ LANGUAGE SQL;
+
+SELECT refresh_team_scores();
+
+DROP FUNCTION IF EXISTS compute_leaderboard(TEXT);
+CREATE FUNCTION compute_leaderboard(division_filter TEXT DEFAULT NULL) RETURNS TABLE (team_id INT, score INT, rank INT) AS $$
+SELECT t.id,
+    COALESCE(ts.score, 0),
+    ROW_NUMBER() OVER (
+        ORDER BY COALESCE(ts.score, 0) DESC,
+            ts.last_solve ASC NULLS LAST,
+            t.id ASC
+    )::int
+FROM teams t
+    LEFT JOIN team_scores ts ON t.id = ts.team_id
+WHERE division_filter IS NULL OR t.division = division_filter $$ LANGUAGE SQL;
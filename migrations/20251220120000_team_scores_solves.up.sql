@@ -0,0 +1,31 @@
+ALTER TABLE team_scores
+ADD COLUMN solves INT NOT NULL DEFAULT 0;
+
+-- recomputes the totals of every team that solved the challenge, or of every team if it's NULL.
+-- refreshes run one at a time so concurrent solves by the same team can't overwrite each other
+-- with totals that miss the other solve.
+CREATE OR REPLACE FUNCTION refresh_team_scores(chall INT DEFAULT NULL) RETURNS VOID AS $$
+SELECT pg_advisory_xact_lock('team_scores'::regclass::oid::bigint);
+DELETE FROM team_scores
+WHERE chall IS NULL;
+INSERT INTO team_scores (team_id, score, solves, last_solve)
+SELECT team_id,
+    SUM(points + bonus),
+    COUNT(*),
+    MAX(created_at)
+FROM scored_solves()
+WHERE chall IS NULL
+    OR team_id IN (
+        SELECT team_id
+        FROM submissions
+        WHERE challenge_id = chall
+            AND is_correct = true
+    )
+GROUP BY team_id ON CONFLICT (team_id) DO
+UPDATE
+SET score = EXCLUDED.score,
+    solves = EXCLUDED.solves,
+    last_solve = EXCLUDED.last_solve;
+$$ LANGUAGE SQL;
+
+SELECT refresh_team_scores();
@@ -0,0 +1,14 @@
+DROP FUNCTION IF EXISTS compute_leaderboard(TEXT[], TEXT);
+CREATE FUNCTION compute_leaderboard(division_filter TEXT DEFAULT NULL) RETURNS TABLE (team_id INT, score INT, rank INT) AS $$
+SELECT t.id,
+    COALESCE(ts.score, 0),
+    ROW_NUMBER() OVER (
+        ORDER BY COALESCE(ts.score, 0) DESC,
+            ts.last_solve ASC NULLS LAST,
+            t.id ASC
+    )::int
+FROM teams t
+    LEFT JOIN team_scores ts ON t.id = ts.team_id
+WHERE division_filter IS NULL OR t.division = division_filter $$ LANGUAGE SQL;
+
+DROP TABLE division_includes;
@@ -0,0 +1,59 @@
+-- synced from the event's division_includes on startup, already transitive
+CREATE TABLE division_includes (
+    division TEXT NOT NULL,
+    -- teams in this division also appear on the leaderboard of `division`
+    includes TEXT NOT NULL,
+    PRIMARY KEY (division, includes)
+);
+
+DROP FUNCTION IF EXISTS compute_leaderboard(TEXT);
+-- ranks the teams eligible for any of the divisions, or every team if NULL. with a category, only
+-- solves of challenges in it count.
+CREATE FUNCTION compute_leaderboard(divisions TEXT[] DEFAULT NULL, category TEXT DEFAULT NULL) RETURNS TABLE (
+    team_id INT,
+    score INT,
+    rank INT,
+    solves INT,
+    last_solve TIMESTAMP
+) AS $$ WITH category_scores AS (
+    SELECT s.team_id,
+        SUM(s.points + s.bonus)::int AS score,
+        COUNT(*)::int AS solves,
+        MAX(s.created_at) AS last_solve
+    FROM scored_solves() s
+        JOIN challenges ch ON ch.id = s.challenge_id
+        JOIN categories c ON c.id = ch.category_id
+    -- skips scored_solves() entirely for the overall leaderboard
+    WHERE category IS NOT NULL
+        AND c.name = category
+    GROUP BY s.team_id
+),
+scores AS (
+    SELECT ts.team_id,
+        ts.score,
+        ts.solves,
+        ts.last_solve
+    FROM team_scores ts
+    WHERE category IS NULL
+    UNION ALL
+    SELECT *
+    FROM category_scores
+)
+SELECT t.id,
+    COALESCE(s.score, 0),
+    ROW_NUMBER() OVER (
+        ORDER BY COALESCE(s.score, 0) DESC,
+            s.last_solve ASC NULLS LAST,
+            t.id ASC
+    )::int,
+    COALESCE(s.solves, 0),
+    s.last_solve
+FROM teams t
+    LEFT JOIN scores s ON t.id = s.team_id
+WHERE divisions IS NULL
+    OR t.division = ANY(divisions)
+    OR t.division IN (
+        SELECT di.includes
+        FROM division_includes di
+        WHERE di.division = ANY(divisions)
+    ) $$ LANGUAGE SQL;
@@ -0,0 +1,3 @@
+ALTER TABLE submissions DROP COLUMN user_id;
+DROP TABLE team_invites;
+DROP TABLE users;
@@ -0,0 +1,23 @@
+CREATE TABLE users (
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    public_id TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    email TEXT NOT NULL UNIQUE,
+    -- NULL until the user creates or joins a team
+    team_id INT REFERENCES teams(id) ON DELETE SET NULL,
+    captain BOOLEAN NOT NULL DEFAULT false,
+    joined_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX users_team_id_idx ON users (team_id);
+CREATE UNIQUE INDEX users_one_captain_idx ON users (team_id) WHERE captain;
+
+-- handed out by members, rotated by the captain
+CREATE TABLE team_invites (
+    team_id INT PRIMARY KEY REFERENCES teams(id) ON DELETE CASCADE,
+    code TEXT NOT NULL UNIQUE
+);
+
+-- NULL for submissions made with the team token
+ALTER TABLE submissions ADD COLUMN user_id INT REFERENCES users(id) ON DELETE SET NULL;
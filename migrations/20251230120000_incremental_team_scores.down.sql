@@ -0,0 +1,58 @@
+-- recomputes the totals of every team that solved the challenge, or of every team if it's NULL.
+-- refreshes run one at a time so concurrent solves by the same team can't overwrite each other
+-- with totals that miss the other solve.
+CREATE OR REPLACE FUNCTION refresh_team_scores(chall INT DEFAULT NULL) RETURNS VOID AS $$
+SELECT pg_advisory_xact_lock('team_scores'::regclass::oid::bigint);
+DELETE FROM team_scores
+WHERE chall IS NULL;
+INSERT INTO team_scores (team_id, score, solves, last_solve)
+SELECT team_id,
+    SUM(points + bonus),
+    COUNT(*),
+    MAX(created_at)
+FROM scored_solves()
+WHERE chall IS NULL
+    OR team_id IN (
+        SELECT team_id
+        FROM submissions
+        WHERE challenge_id = chall
+            AND is_correct = true
+    )
+GROUP BY team_id ON CONFLICT (team_id) DO
+UPDATE
+SET score = EXCLUDED.score,
+    solves = EXCLUDED.solves,
+    last_solve = EXCLUDED.last_solve;
+$$ LANGUAGE SQL;
+
+DROP FUNCTION IF EXISTS scored_solves(INT);
+CREATE FUNCTION scored_solves() RETURNS TABLE (
+    team_id INT,
+    challenge_id INT,
+    created_at TIMESTAMP,
+    place INT,
+    points INT,
+    bonus INT
+) AS $$ WITH solves AS (
+    SELECT team_id,
+        challenge_id,
+        created_at,
+        ROW_NUMBER() OVER (
+            PARTITION BY challenge_id
+            ORDER BY created_at ASC,
+                id ASC
+        )::int AS place
+    FROM submissions
+    WHERE is_correct = true
+)
+SELECT s.team_id,
+    s.challenge_id,
+    s.created_at,
+    s.place,
+    ch.c_points,
+    COALESCE(bb.points + CEIL(ch.c_points * bb.percent / 100.0)::int, 0)
+FROM solves s
+    JOIN challenges ch ON ch.id = s.challenge_id
+    LEFT JOIN blood_bonuses bb ON bb.place = s.place $$ LANGUAGE SQL;
+
+DROP TABLE solve_scores;
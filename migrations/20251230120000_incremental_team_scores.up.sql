@@ -0,0 +1,88 @@
+-- what each solve currently adds to its team's total, so refreshing a challenge only has to apply
+-- the difference to the teams that solved it
+CREATE TABLE solve_scores (
+    challenge_id INT NOT NULL REFERENCES challenges(id) ON DELETE CASCADE,
+    team_id INT NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    -- points + blood bonus
+    points INT NOT NULL,
+    solved_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (challenge_id, team_id)
+);
+
+-- same as before, but only ranks the solves of `chall` when it's given
+DROP FUNCTION IF EXISTS scored_solves();
+CREATE FUNCTION scored_solves(chall INT DEFAULT NULL) RETURNS TABLE (
+    team_id INT,
+    challenge_id INT,
+    created_at TIMESTAMP,
+    place INT,
+    points INT,
+    bonus INT
+) AS $$ WITH solves AS (
+    SELECT team_id,
+        challenge_id,
+        created_at,
+        ROW_NUMBER() OVER (
+            PARTITION BY challenge_id
+            ORDER BY created_at ASC,
+                id ASC
+        )::int AS place
+    FROM submissions
+    WHERE is_correct = true
+        AND (chall IS NULL OR challenge_id = chall)
+)
+SELECT s.team_id,
+    s.challenge_id,
+    s.created_at,
+    s.place,
+    ch.c_points,
+    COALESCE(bb.points + CEIL(ch.c_points * bb.percent / 100.0)::int, 0)
+FROM solves s
+    JOIN challenges ch ON ch.id = s.challenge_id
+    LEFT JOIN blood_bonuses bb ON bb.place = s.place $$ LANGUAGE SQL;
+
+-- moves every solver of the challenge from its old solve_scores to the new ones, or rebuilds
+-- everything if it's NULL. refreshes of the same challenge run one at a time so neither applies
+-- its difference against stale solve_scores, and full rebuilds wait for all of them.
+CREATE OR REPLACE FUNCTION refresh_team_scores(chall INT DEFAULT NULL) RETURNS VOID AS $$
+SELECT pg_advisory_xact_lock('team_scores'::regclass::oid::bigint)
+WHERE chall IS NULL;
+SELECT pg_advisory_xact_lock_shared('team_scores'::regclass::oid::bigint)
+WHERE chall IS NOT NULL;
+SELECT pg_advisory_xact_lock('solve_scores'::regclass::oid::int, chall)
+WHERE chall IS NOT NULL;
+
+DELETE FROM team_scores
+WHERE chall IS NULL;
+UPDATE team_scores ts
+SET score = ts.score - ss.points,
+    solves = ts.solves - 1
+FROM solve_scores ss
+WHERE ss.challenge_id = chall
+    AND ss.team_id = ts.team_id;
+DELETE FROM solve_scores
+WHERE chall IS NULL
+    OR challenge_id = chall;
+
+INSERT INTO solve_scores (challenge_id, team_id, points, solved_at)
+SELECT challenge_id,
+    team_id,
+    points + bonus,
+    created_at
+FROM scored_solves(chall);
+INSERT INTO team_scores (team_id, score, solves, last_solve)
+SELECT team_id,
+    SUM(points),
+    COUNT(*),
+    MAX(solved_at)
+FROM solve_scores
+WHERE chall IS NULL
+    OR challenge_id = chall
+GROUP BY team_id ON CONFLICT (team_id) DO
+UPDATE
+SET score = team_scores.score + EXCLUDED.score,
+    solves = team_scores.solves + EXCLUDED.solves,
+    last_solve = GREATEST(team_scores.last_solve, EXCLUDED.last_solve);
+$$ LANGUAGE SQL;
+
+SELECT refresh_team_scores();